@@ -3,26 +3,34 @@
 
 /// Utilities for creating and managing perfjail processes
 pub mod process;
+/// Utilities for restricting the system calls perfjail child processes can make
+pub mod seccomp;
 /// Utilities for setting Linux up for perfjail use
 pub mod setup;
 
 mod listener;
 mod util;
-mod seccomp;
 
 #[cfg(test)]
 mod tests {
     use std::fs::File;
-    use std::os::fd::AsFd;
-    use std::sync::{Arc, Mutex};
+    use std::io::{Read, Write};
+    use std::os::fd::{AsFd, AsRawFd};
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+    use std::sync::{mpsc, Arc, Mutex};
     use std::thread;
     use std::time::Duration;
 
+    use nix::sched::sched_getaffinity;
+    use nix::unistd::Pid;
+
     use crate::process::execution_result::ExitReason::Exited;
-    use crate::process::{ExecutionResult, ExitReason, ExitStatus};
-    use crate::process::Feature::{MEMORY_MEASUREMENT, TIME_MEASUREMENT};
+    use crate::process::{ExecutionResult, ExitReason, ExitStatus, IoPriority, OutputPump, Resource, SchedulingPolicy, SharedInput, Stdio, RLIM_INFINITY};
+    use crate::process::Feature::{MEMORY_MEASUREMENT, SYSCALL_AUDIT, TIME_MEASUREMENT};
     use crate::process::jail::Feature::PERF;
     use crate::process::jail::Perfjail;
+    use crate::seccomp::{ArgumentCondition, SeccompAction, SyscallAbi, SyscallDecision, SyscallPolicy};
 
     #[test]
     fn time_measurement_test() {
@@ -32,8 +40,8 @@ mod tests {
         let output_file = File::create("tests/test_output.out").unwrap();
 
         let child = Perfjail::new("tests/bud")
-            .stdin(input_file.as_fd())
-            .stdout(output_file.as_fd())
+            .stdin(Stdio::try_from(input_file.as_fd()).unwrap())
+            .stdout(Stdio::try_from(output_file.as_fd()).unwrap())
             .features(PERF | TIME_MEASUREMENT | MEMORY_MEASUREMENT)
             .measured_time_limit(Duration::from_millis(500))
             .spawn()
@@ -103,4 +111,684 @@ mod tests {
 
         assert_eq!(*child_result.lock().unwrap(), result);
     }
+
+    #[test]
+    fn signal_under_ptrace_test() {
+        let result = Perfjail::new("sh")
+            .args(["-c", "kill -SEGV $$"])
+            .features(MEMORY_MEASUREMENT)
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert_eq!(result.exit_reason, ExitReason::Killed { signal: 11 });
+    }
+
+    #[test]
+    fn stack_limit_test() {
+        let result = Perfjail::new("sh")
+            .args(["-c", "test $(ulimit -s) = 1024"])
+            .features(MEMORY_MEASUREMENT)
+            .stack_limit_kibibytes(1024)
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert_eq!(result.exit_status, ExitStatus::OK);
+        let stack_usage = result.stack_usage_kibibytes.unwrap();
+        assert!(stack_usage > 0 && stack_usage <= 1024);
+        assert!(result.data_usage_kibibytes.unwrap() > 0);
+        assert!(result.memory_usage_kibibytes.unwrap() >= result.data_usage_kibibytes.unwrap());
+    }
+
+    #[test]
+    fn rlimit_test() {
+        // The stack limit set by the user takes priority over the one raised by memory measurement
+        let result = Perfjail::new("sh")
+            .args(["-c", "test $(ulimit -s) = 2048 && test $(ulimit -n) = 32"])
+            .features(MEMORY_MEASUREMENT)
+            .rlimit(Resource::RLIMIT_STACK, 2048 * 1024, RLIM_INFINITY)
+            .rlimit(Resource::RLIMIT_NOFILE, 16, 16)
+            .rlimit(Resource::RLIMIT_NOFILE, 32, 32)
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert_eq!(result.exit_status, ExitStatus::OK);
+    }
+
+    #[test]
+    fn cpu_time_backstop_test() {
+        // Only the system time of dd grows, so the supervisor never kills it for exceeding the user time limit
+        let result = Perfjail::new("dd")
+            .args(["if=/dev/zero", "of=/dev/null", "bs=1M"])
+            .user_time_limit(Duration::from_millis(500))
+            .cpu_time_backstop(Duration::from_millis(200))
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert_eq!(result.exit_status, ExitStatus::TLE("time limit exceeded".into()));
+        assert_eq!(result.exit_reason, ExitReason::Killed { signal: libc::SIGXCPU });
+
+        // Without a backstop, the signal is an ordinary runtime error
+        let result = Perfjail::new("sh").args(["-c", "kill -XCPU $$"]).spawn().unwrap().run().unwrap();
+        assert_eq!(result.exit_status, ExitStatus::RE(format!("runtime error: killed by signal {}", libc::SIGXCPU)));
+    }
+
+    #[test]
+    fn cpu_affinity_test() {
+        let affinity = sched_getaffinity(Pid::from_raw(0)).unwrap();
+        let result = Perfjail::new("sh")
+            .args(["-c", "test $(nproc) = 1"])
+            .cpu_affinity([0])
+            .supervisor_cpu_affinity([0])
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert_eq!(result.exit_status, ExitStatus::OK);
+        assert_eq!(sched_getaffinity(Pid::from_raw(0)).unwrap(), affinity);
+        // There are no machines with that many CPUs
+        assert!(Perfjail::new("true").cpu_affinity([1000]).spawn().unwrap().run().is_err());
+    }
+
+    #[test]
+    fn scheduling_test() {
+        let result = Perfjail::new("sh")
+            .args(["-c", "test $(nice) = 10 && chrt -p $$ | grep -q SCHED_BATCH && ionice -p $$ | grep -q idle"])
+            .nice(10)
+            .scheduling_policy(SchedulingPolicy::BATCH)
+            .io_priority(IoPriority::IDLE)
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert_eq!(result.exit_status, ExitStatus::OK);
+        assert!(Perfjail::new("true").io_priority(IoPriority::BEST_EFFORT(8)).spawn().unwrap().run().is_err());
+    }
+
+    #[test]
+    fn env_test() {
+        let output_path = std::env::temp_dir().join("perfjail_env_test.out");
+        let child = Perfjail::new("sh")
+            .args(["-c", "echo $HOME; test \"$(env | sort)\" = \"$(printf 'A=1\nHOME=%s\nPWD=%s\nTMPDIR=%s' $HOME $PWD $HOME)\""])
+            .env("A", "0")
+            .envs([("A", "1"), ("B", "2")])
+            .env_remove("B")
+            .fresh_home()
+            .env_clear()
+            .env("A", "1")
+            .stdout_pump(OutputPump::new().tee(File::create(&output_path).unwrap()))
+            .spawn()
+            .unwrap();
+
+        assert_eq!(child.run().unwrap().exit_status, ExitStatus::OK);
+        let home_dir = std::fs::read_to_string(&output_path).unwrap();
+        assert!(std::fs::metadata(home_dir.trim()).unwrap().is_dir());
+        drop(child);
+        assert!(std::fs::metadata(home_dir.trim()).is_err());
+
+        let error = Perfjail::new("true").env("A", "\0").spawn().err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn close_inherited_fds_test() {
+        // Unlike the file itself, its duplicate isn't closed on execution
+        let file = File::open("/dev/null").unwrap();
+        let inherited_fd = unsafe { libc::dup(file.as_raw_fd()) };
+        let run = |close_inherited_fds| Perfjail::new("sh")
+            .args(["-c", &format!("test -e /proc/$$/fd/{inherited_fd}")])
+            .features(MEMORY_MEASUREMENT)
+            .close_inherited_fds(close_inherited_fds)
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        let result = run(true);
+        assert_eq!(result.exit_reason, ExitReason::Exited { exit_status: 1 });
+        // The pipe of the memory listener has to stay open until the program is executed
+        assert!(result.memory_usage_kibibytes.unwrap() < 64 * 1024);
+        assert_eq!(run(false).exit_status, ExitStatus::OK);
+        unsafe { libc::close(inherited_fd) };
+    }
+
+    #[test]
+    fn fd_map_test() {
+        let (first_read, mut first_write) = std::io::pipe().unwrap();
+        let (second_read, mut second_write) = std::io::pipe().unwrap();
+        first_write.write_all(b"first\n").unwrap();
+        second_write.write_all(b"second\n").unwrap();
+
+        let result = Perfjail::new("sh")
+            .args(["-c", "read a <&3 && read b <&4 && test \"$a $b\" = \"second first\""])
+            .features(MEMORY_MEASUREMENT)
+            .fd_map(3, second_read)
+            .fd_map(4, first_read)
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert_eq!(result.exit_status, ExitStatus::OK);
+        assert!(result.memory_usage_kibibytes.unwrap() < 64 * 1024);
+    }
+
+    #[test]
+    fn seccomp_kill_test() {
+        let result = Perfjail::new("true")
+            .syscall_policy(SyscallPolicy::new(SeccompAction::KILL))
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        let violation = result.syscall_violation.as_ref().unwrap();
+        assert_eq!((violation.abi, violation.name, violation.rule.as_deref()), (Some(SyscallAbi::NATIVE), Some("brk"), None));
+        assert_eq!(result.exit_status, ExitStatus::RV(violation.to_string()));
+        assert!(result.exit_status.get_exit_status_comment().starts_with("intercepted forbidden syscall brk(0x0, "));
+        assert_eq!(result.exit_reason, ExitReason::Killed { signal: 9 });
+    }
+
+    #[test]
+    fn seccomp_fresh_thread_test() {
+        // A new thread has no malloc arena yet, so allocating in the child after the filter is installed
+        // would make a system call the policy forbids before the supervisor is ready to handle it
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let result = Perfjail::new("true")
+                .syscall_policy(SyscallPolicy::new(SeccompAction::KILL))
+                .spawn()
+                .unwrap()
+                .run()
+                .unwrap();
+            sender.send(result).unwrap();
+        });
+
+        let result = receiver.recv_timeout(Duration::from_secs(10)).expect("the child has hung");
+        assert_eq!(result.syscall_violation.as_ref().unwrap().name, Some("brk"));
+    }
+
+    #[test]
+    fn seccomp_errno_test() {
+        let result = Perfjail::new("sh")
+            .args(["-c", "exec true"])
+            .syscall_policy(SyscallPolicy::new(SeccompAction::ALLOW).rule("execve", SeccompAction::ERRNO(libc::EPERM)))
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert_eq!(result.exit_reason, ExitReason::Exited { exit_status: 126 });
+    }
+
+    #[test]
+    fn seccomp_fork_exec_test() {
+        let run = |policy| Perfjail::new("sh")
+            .args(["-c", "/bin/true && /bin/true"])
+            .syscall_policy(policy)
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        // Programs executed by processes the child has forked are subject to the policy as well
+        assert_eq!(run(SyscallPolicy::new(SeccompAction::ALLOW)).exit_status, ExitStatus::OK);
+        let result = run(SyscallPolicy::new(SeccompAction::ALLOW).rule("execve", SeccompAction::KILL));
+        assert_eq!(result.syscall_violation.as_ref().unwrap().name, Some("execve"));
+    }
+
+    #[test]
+    fn seccomp_argument_condition_test() {
+        let result = Perfjail::new("sh")
+            .args(["-c", "kill -0 $$ && kill -0 1"])
+            .syscall_policy(
+                SyscallPolicy::new(SeccompAction::ALLOW)
+                    .rule_if("kill", [ArgumentCondition::child_pid(0)], SeccompAction::ALLOW)
+                    .rule("kill", SeccompAction::KILL)
+            )
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        let violation = result.syscall_violation.as_ref().unwrap();
+        assert_eq!((violation.name, &violation.args[..2]), (Some("kill"), &[1, 0][..]));
+        assert_eq!(violation.rule.as_deref(), Some("kill kill"));
+        assert!(result.exit_status.get_exit_status_comment().starts_with("intercepted forbidden syscall kill(0x1, 0x0, "));
+    }
+
+    #[test]
+    fn seccomp_syscall_handler_test() {
+        let traced_syscalls = Arc::new(Mutex::new(Vec::new()));
+        let handler_traced_syscalls = traced_syscalls.clone();
+
+        let result = Perfjail::new("mkdir")
+            .arg("/tmp/perfjail_syscall_handler_test")
+            .syscall_policy(SyscallPolicy::new(SeccompAction::ALLOW).rule("mkdir", SeccompAction::TRACE))
+            .syscall_handler(move |syscall, _: &[u64; 6], _| {
+                handler_traced_syscalls.lock().unwrap().push(syscall);
+                SyscallDecision::ERRNO(libc::EACCES)
+            })
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert_eq!(result.exit_reason, ExitReason::Exited { exit_status: 1 });
+        assert_eq!(*traced_syscalls.lock().unwrap(), vec![libc::SYS_mkdir]);
+    }
+
+    #[test]
+    fn seccomp_forked_process_test() {
+        let result = Perfjail::new("sh")
+            .args(["-c", "(kill -0 1); exit 0"])
+            .syscall_policy(SyscallPolicy::new(SeccompAction::ALLOW).rule("kill", SeccompAction::KILL))
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert_eq!(result.syscall_violation.as_ref().unwrap().name, Some("kill"));
+        assert!(result.exit_status.get_exit_status_comment().starts_with("intercepted forbidden syscall kill(0x1, 0x0, "));
+    }
+
+    #[test]
+    fn seccomp_foreign_abi_test() {
+        // getpid made through the x32 ABI, which is forbidden even though the policy allows everything
+        let result = Perfjail::new("perl")
+            .args(["-e", "syscall(0x40000027)"])
+            .syscall_policy(SyscallPolicy::new(SeccompAction::ALLOW))
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        let violation = result.syscall_violation.as_ref().unwrap();
+        assert_eq!((violation.abi, violation.number, violation.name), (Some(SyscallAbi::X32), 0x40000027, None));
+        assert!(result.exit_status.get_exit_status_comment().ends_with(") made through the x32 ABI"));
+    }
+
+    #[test]
+    fn syscall_audit_test() {
+        let result = Perfjail::new("sh")
+            .args(["-c", "echo first; (echo second)"])
+            .features(SYSCALL_AUDIT)
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        let syscall_counts = result.syscall_counts.as_ref().expect("syscall counts not returned");
+        let count = |name| syscall_counts.iter()
+            .find(|syscall_count| syscall_count.name == Some(name))
+            .map_or(0, |syscall_count| syscall_count.count);
+        assert!(syscall_counts.is_sorted_by_key(|syscall_count| syscall_count.number));
+        // The subshell is a forked process, whose system calls are counted as well
+        assert_eq!(count("write"), 2);
+        assert_eq!(count("exit_group"), 2);
+        assert_eq!(count("execve"), 0);
+        assert_eq!(result.exit_reason, Exited { exit_status: 0 });
+    }
+
+    #[test]
+    fn output_limit_test() {
+        let output_path = std::env::temp_dir().join("perfjail_output_limit_test.out");
+        let output_file = File::create(&output_path).unwrap();
+
+        let result = Perfjail::new("yes")
+            .stdout(output_file)
+            .output_limit_bytes(4096)
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert_eq!(result.exit_status, ExitStatus::OLE("output limit exceeded".into()));
+        assert_eq!(std::fs::metadata(&output_path).unwrap().len(), 4096);
+
+        // Without an output limit, the signal is an ordinary runtime error
+        let result = Perfjail::new("sh").args(["-c", "kill -XFSZ $$"]).spawn().unwrap().run().unwrap();
+        assert_eq!(result.exit_status, ExitStatus::RE(format!("runtime error: killed by signal {}", libc::SIGXFSZ)));
+    }
+
+    #[test]
+    fn output_pump_test() {
+        let tee_path = std::env::temp_dir().join("perfjail_output_pump_test.out");
+        let result = Perfjail::new("sh")
+            .args(["-c", "echo hello; echo error >&2; yes"])
+            .stdout_pump(OutputPump::new().limit_bytes(1 << 20).tee(File::create(&tee_path).unwrap()))
+            .stderr_pump(OutputPump::new())
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert_eq!(result.exit_status, ExitStatus::OLE("output limit exceeded".into()));
+        let stdout_digest = result.stdout_digest.as_ref().unwrap();
+        assert_eq!(stdout_digest.length, 1 << 20);
+        assert_eq!(std::fs::metadata(&tee_path).unwrap().len(), 1 << 20);
+        let stderr_digest = result.stderr_digest.as_ref().unwrap();
+        assert_eq!(stderr_digest.length, 6);
+        assert_eq!(stderr_digest.sha256_hex(), "f097b5f4f46cda2da21b954c9ff4097e1e14ae7064ecdee2c2cec2d3c1f08e6b");
+    }
+
+    #[test]
+    fn output_test() {
+        let output = Perfjail::new("sh")
+            .args(["-c", "echo hello; head -c 100000 /dev/zero >&2"])
+            .stderr_pump(OutputPump::new().capture(10))
+            .output()
+            .unwrap();
+
+        assert_eq!(output.result.exit_status, ExitStatus::OK);
+        assert_eq!((output.stdout.as_slice(), output.stdout_truncated), (&b"hello\n"[..], false));
+        assert_eq!((output.stderr.as_slice(), output.stderr_truncated), (&[0; 10][..], true));
+        assert_eq!(output.result.stderr_digest.as_ref().unwrap().length, 100000);
+    }
+
+    #[test]
+    fn stdin_bytes_test() {
+        // The input is larger than a pipe buffer, so it couldn't be written to a pipe before the child is run
+        let output = Perfjail::new("sh")
+            .args(["-c", "test \"$(stat -L -c %F /dev/stdin)\" = \"regular file\" && wc -c"])
+            .stdin_bytes(vec![b'a'; 1 << 20])
+            .stdout_pump(OutputPump::new().capture(1024))
+            .output()
+            .unwrap();
+
+        assert_eq!(output.result.exit_status, ExitStatus::OK);
+        assert_eq!(output.stdout, b"1048576\n");
+    }
+
+    #[test]
+    fn stdin_last_call_wins_test() {
+        let run = |jail: Perfjail| jail.stdout_pump(OutputPump::new().capture(1024)).output().unwrap().stdout;
+
+        assert_eq!(run(Perfjail::new("wc").arg("-c").stdin_bytes("abc").stdin(Stdio::Null)), b"0\n");
+        assert_eq!(run(Perfjail::new("wc").arg("-c").stdin(Stdio::Null).stdin_bytes("abc")), b"3\n");
+
+        let error = Perfjail::new("true").stdout(Stdio::Bytes(b"abc".to_vec())).spawn().err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn shared_input_test() {
+        let input = SharedInput::from_reader(&[b'a'; 100000][..]).unwrap();
+        let run = |command| Perfjail::new("sh")
+            .args(["-c", command])
+            .stdin(input.reopen().unwrap())
+            .stdout_pump(OutputPump::new().capture(1024))
+            .output()
+            .unwrap();
+
+        // Each run has its own offset, so reading a part of the input doesn't affect the others
+        assert_eq!(run("head -c 10 >/dev/null").result.exit_status, ExitStatus::OK);
+        let outputs: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..4).map(|_| scope.spawn(|| run("wc -c"))).collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+        assert!(outputs.iter().all(|output| output.stdout == b"100000\n"));
+        // The input is sealed, so the children can't modify it
+        assert_ne!(run("echo b >/dev/stdin").result.exit_status, ExitStatus::OK);
+    }
+
+    #[test]
+    fn piped_stdio_test() {
+        let mut child = Perfjail::new("tr")
+            .args(["a-z", "A-Z"])
+            .stdin(Stdio::Piped)
+            .stdout(Stdio::Piped)
+            .stderr(Stdio::Null)
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        let mut stdout = child.stdout.take().unwrap();
+
+        // The child is run on another thread, as it doesn't borrow anything from the parent
+        let runner = thread::spawn(move || child.run());
+        stdin.write_all(b"hello").unwrap();
+        drop(stdin);
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).unwrap();
+
+        assert_eq!(runner.join().unwrap().unwrap().exit_status, ExitStatus::OK);
+        assert_eq!(output, "HELLO");
+    }
+
+    #[test]
+    fn std_stdio_test() {
+        let mut child = Perfjail::new("sh")
+            .args(["-c", "tr a-z A-Z; echo error >&2"])
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        let mut stdout = child.stdout.take().unwrap();
+
+        let runner = thread::spawn(move || child.run());
+        stdin.write_all(b"hello").unwrap();
+        drop(stdin);
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).unwrap();
+
+        assert_eq!(runner.join().unwrap().unwrap().exit_status, ExitStatus::OK);
+        assert_eq!(output, "HELLO");
+    }
+
+    #[test]
+    fn borrowed_stdio_test() {
+        let output_path = std::env::temp_dir().join("perfjail_borrowed_stdio_test.out");
+        let output_file = File::create(&output_path).unwrap();
+        let child = Perfjail::new("echo")
+            .arg("hello")
+            .stdin(Stdio::null())
+            .stdout(Stdio::try_from(output_file.as_fd()).unwrap())
+            .stderr(std::io::stderr())
+            .spawn()
+            .unwrap();
+
+        // The borrowed descriptor is duplicated, so it can be closed before the child is run
+        drop(output_file);
+        assert_eq!(child.run().unwrap().exit_status, ExitStatus::OK);
+        assert_eq!(std::fs::read_to_string(&output_path).unwrap(), "hello\n");
+    }
+
+    #[test]
+    fn thread_limit_test() {
+        let run = |command, thread_limit| Perfjail::new("sh")
+            .args(["-c", command])
+            .thread_limit(thread_limit)
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        // Only running threads and processes count towards the limit
+        assert_eq!(run("true & wait; true & wait", 1).exit_status, ExitStatus::OK);
+        assert_eq!(run("sleep 1 & sleep 1 & wait", 1).exit_status, ExitStatus::RV("thread limit exceeded".into()));
+        assert_eq!(run("true & wait", 0).exit_status, ExitStatus::RV("thread limit exceeded".into()));
+    }
+
+    #[test]
+    fn process_group_test() {
+        // A forked process tries to leave the process group of the child, which would hide it from the supervisor
+        let script = "if (fork()) { wait; exit($? >> 8) } exit(POSIX::setsid() != -1 || defined(POSIX::setpgid(0, 0)))";
+        for jail in [
+            Perfjail::new("perl").syscall_policy(SyscallPolicy::new(SeccompAction::ALLOW)),
+            Perfjail::new("perl").thread_limit(10),
+            Perfjail::new("perl").features(SYSCALL_AUDIT),
+        ] {
+            let result = jail.args(["-MPOSIX", "-e", script]).spawn().unwrap().run().unwrap();
+            assert_eq!(result.exit_reason, ExitReason::Exited { exit_status: 0 });
+        }
+    }
+
+    #[test]
+    fn audited_policy_test() {
+        let run = |policy: Option<SyscallPolicy>| {
+            let jail = Perfjail::new("sh").args(["-c", "echo audited"]);
+            match policy {
+                Some(policy) => jail.syscall_policy(policy),
+                None => jail.features(SYSCALL_AUDIT),
+            }.spawn().unwrap().run().unwrap()
+        };
+
+        let policy = SyscallPolicy::from_audited_executions([&run(None), &run(None)]).unwrap();
+        let serialized_policy = policy.to_string();
+        assert!(serialized_policy.starts_with("default kill\n"));
+        assert!(serialized_policy.lines().any(|line| line == "allow write"));
+
+        let result = run(Some(policy));
+        assert_eq!(result.exit_status, ExitStatus::OK);
+
+        // Rules can't be added for system calls perfjail doesn't know the names of
+        let result = Perfjail::new("perl").args(["-e", "syscall(1000)"]).features(SYSCALL_AUDIT).spawn().unwrap().run().unwrap();
+        let error = SyscallPolicy::from_audited_executions([&result]).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+        assert_eq!(error.to_string(), "the executions made system calls unknown to perfjail: 1000 (native)");
+    }
+
+    /// Compiles the C++ program with g++ into the temporary directory, or returns [`None`] if g++ isn't installed.
+    fn compile_cpp(source: &Path, name: &str, args: &[&str]) -> Option<PathBuf> {
+        let binary = std::env::temp_dir().join(name);
+        let status = Command::new("g++").args(["-O2", "-o"]).arg(&binary).arg(source).args(args).status().ok()?;
+        assert!(status.success(), "failed to compile {}", source.display());
+        Some(binary)
+    }
+
+    #[test]
+    fn cpp_policy_test() {
+        let run = |program: &Path, policy| Perfjail::new(program)
+            .stdin(File::open("tests/bud.in").unwrap())
+            .stdout(Stdio::Null)
+            .syscall_policy(policy)
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        let Some(dynamic) = compile_cpp(Path::new("tests/bud.cpp"), "perfjail_cpp_policy_test", &[]) else { return };
+        assert_eq!(run(&dynamic, SyscallPolicy::cpp().allow_reading_files()).exit_status, ExitStatus::OK);
+        let static_binary = compile_cpp(Path::new("tests/bud.cpp"), "perfjail_cpp_policy_test_static", &["-static"]).unwrap();
+        assert_eq!(run(&static_binary, SyscallPolicy::sio2jail_default()).exit_status, ExitStatus::OK);
+        assert_eq!(run(&static_binary, SyscallPolicy::cpp()).exit_status, ExitStatus::OK);
+
+        // Dynamically linked system utilities probe the file systems and list directories
+        assert_eq!(run(Path::new("/usr/bin/ls"), SyscallPolicy::cpp().allow_reading_files()).exit_status, ExitStatus::OK);
+
+        // Without the relaxation, shared libraries can't be loaded, and neither can any other file be read
+        assert_ne!(run(&dynamic, SyscallPolicy::cpp()).exit_status, ExitStatus::OK);
+        let source = std::env::temp_dir().join("perfjail_cpp_policy_test_read.cpp");
+        std::fs::write(&source, "#include <fstream>\n\
+            int main() { return std::ifstream(\"/etc/passwd\").is_open(); }\n").unwrap();
+        let reading = compile_cpp(&source, "perfjail_cpp_policy_test_read", &["-static"]).unwrap();
+        assert_eq!(run(&reading, SyscallPolicy::cpp()).exit_status, ExitStatus::OK);
+        assert_eq!(run(&reading, SyscallPolicy::cpp().allow_reading_files()).exit_reason, ExitReason::Exited { exit_status: 1 });
+    }
+
+    #[test]
+    fn priority_policy_test() {
+        let source = std::env::temp_dir().join("perfjail_priority_policy_test.cpp");
+        std::fs::write(&source, "#include <sys/resource.h>\n\
+            int main() { setpriority(PRIO_PROCESS, 0, 1); setpriority(PRIO_PROCESS, 1, 0); }\n").unwrap();
+        let Some(binary) = compile_cpp(&source, "perfjail_priority_policy_test", &[]) else { return };
+
+        // The child can lower its own priority, but not change the priority of other processes
+        let result = Perfjail::new(binary).syscall_policy(SyscallPolicy::cpp().allow_reading_files()).spawn().unwrap().run().unwrap();
+        let violation = result.syscall_violation.as_ref().unwrap();
+        assert_eq!((violation.name, &violation.args[..2]), (Some("setpriority"), &[libc::PRIO_PROCESS as u64, 1][..]));
+    }
+
+    #[test]
+    fn python_policy_test() {
+        if !Path::new("/usr/bin/python3").exists() {
+            return;
+        }
+
+        let result = Perfjail::new("/usr/bin/python3")
+            .args(["-c", "print(sum(map(int, input().split())))"])
+            .stdin(Stdio::Path("tests/bud.in".into()))
+            .stdout(Stdio::Null)
+            .syscall_policy(SyscallPolicy::python())
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert_eq!(result.exit_status, ExitStatus::OK);
+    }
+
+    #[test]
+    fn java_policy_test() {
+        let directory = std::env::temp_dir().join("perfjail_java_policy_test");
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("Main.java"), "public class Main {\n\
+            public static void main(String[] args) { System.out.println(new java.util.Scanner(System.in).nextInt()); }\n\
+        }\n").unwrap();
+        let Ok(status) = Command::new("javac").arg("Main.java").current_dir(&directory).status() else { return };
+        assert!(status.success());
+
+        let result = Perfjail::new("java")
+            .args(["-XX:-UsePerfData", "Main"])
+            .current_dir(&directory)
+            .stdin(Stdio::Path("tests/bud.in".into()))
+            .stdout(Stdio::Null)
+            .syscall_policy(SyscallPolicy::java())
+            .spawn()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert_eq!(result.exit_status, ExitStatus::OK);
+    }
+
+    #[test]
+    fn policy_file_test() {
+        for policy in [
+            SyscallPolicy::sio2jail_default().i386_policy(SyscallPolicy::sio2jail_i386()),
+            SyscallPolicy::cpp().allow_reading_files(),
+            SyscallPolicy::java(),
+            // Zero and values without a name are written as numbers
+            SyscallPolicy::new(SeccompAction::ERRNO(0)).rule("read", SeccompAction::ERRNO(1000)),
+        ] {
+            assert_eq!(policy.to_string().parse::<SyscallPolicy>(), Ok(policy));
+        }
+
+        let policy: SyscallPolicy = "
+            group signals  # only to the child itself
+                allow kill if arg0 == child_pid
+                allow tgkill if arg0 == child_pid and arg1 != 0
+            end
+
+            default errno(EPERM)
+            use signals
+            kill kill
+            allow mmap if arg2 & 0x4 == 0x0
+        ".parse().unwrap();
+        assert_eq!(policy, SyscallPolicy::new(SeccompAction::ERRNO(libc::EPERM))
+            .rule_if("kill", [ArgumentCondition::child_pid(0)], SeccompAction::ALLOW)
+            .rule_if("tgkill", [ArgumentCondition::child_pid(0), ArgumentCondition::not_equal(1, 0)], SeccompAction::ALLOW)
+            .rule("kill", SeccompAction::KILL)
+            .rule_if("mmap", [ArgumentCondition::masked_equal(2, 4, 0)], SeccompAction::ALLOW));
+
+        let error = "default kill\nuse files".parse::<SyscallPolicy>().unwrap_err();
+        assert_eq!((error.line(), error.message()), (2, "unknown group `files`"));
+        let error = "default errno(-1)".parse::<SyscallPolicy>().unwrap_err();
+        assert_eq!((error.line(), error.message()), (1, "unknown action `errno(-1)`"));
+        let error = "allow read".parse::<SyscallPolicy>().unwrap_err();
+        assert_eq!((error.line(), error.message()), (1, "expected a `default` statement before the first rule"));
+        let error = "default kill\n\nallow mmap if arg2 & 0x4 > 0".parse::<SyscallPolicy>().unwrap_err();
+        assert_eq!((error.line(), error.message()), (3, "masked arguments can only be compared using `==`"));
+    }
 }
@@ -16,6 +16,19 @@ pub(crate) struct MemoryListener {
     parent: RawFd,
     closed_child_in_parent: bool,
     peak_memory_kibibytes: u64,
+    /// The largest sampled `VmStk` and `VmData` values, as the kernel doesn't track their peaks.
+    peak_stack_kibibytes: u64,
+    peak_data_kibibytes: u64,
+}
+
+/// The memory usage values of the child read from `/proc/<pid>/status`, in kibibytes.
+struct MemoryUsage {
+    /// The `VmPeak` value.
+    peak: u64,
+    /// The `VmStk` value, which unlike `VmPeak` is only the current size.
+    stack: u64,
+    /// The `VmData` value, which unlike `VmPeak` is only the current size.
+    data: u64,
 }
 
 impl MemoryListener {
@@ -29,6 +42,8 @@ impl MemoryListener {
             parent: read.into_raw_fd(),
             closed_child_in_parent: false,
             peak_memory_kibibytes: 0,
+            peak_stack_kibibytes: 0,
+            peak_data_kibibytes: 0,
         }
     }
 }
@@ -58,9 +73,11 @@ impl Listener for MemoryListener {
 
     fn on_wakeup(&mut self, settings: &ExecutionSettings, data: &mut ExecutionData) -> io::Result<WakeupAction> {
         if self.was_exec_called() {
-            self.peak_memory_kibibytes = self.peak_memory_kibibytes.max(
-                MemoryListener::get_peak_memory_usage(data).unwrap_or(0)
-            );
+            if let Some(usage) = MemoryListener::get_memory_usage(data) {
+                self.peak_memory_kibibytes = self.peak_memory_kibibytes.max(usage.peak);
+                self.peak_stack_kibibytes = self.peak_stack_kibibytes.max(usage.stack);
+                self.peak_data_kibibytes = self.peak_data_kibibytes.max(usage.data);
+            }
 
             if let Some(limit) = settings.memory_limit_kibibytes && self.peak_memory_kibibytes > limit {
                 data.execution_result.set_exit_status(ExitStatus::MLE("memory limit exceeded".into()));
@@ -77,6 +94,8 @@ impl Listener for MemoryListener {
 
     fn on_post_execute(&mut self, settings: &ExecutionSettings, data: &mut ExecutionData) -> io::Result<()> {
         data.execution_result.set_memory_usage_kibibytes(self.peak_memory_kibibytes);
+        data.execution_result.set_stack_usage_kibibytes(self.peak_stack_kibibytes);
+        data.execution_result.set_data_usage_kibibytes(self.peak_data_kibibytes);
         if let Some(limit) = settings.memory_limit_kibibytes && self.peak_memory_kibibytes > limit {
             data.execution_result.set_exit_status(ExitStatus::MLE("memory limit exceeded".into()));
         }
@@ -111,25 +130,27 @@ impl MemoryListener {
         }
     }
 
-    fn get_peak_memory_usage(data: &ExecutionData) -> Option<u64> {
+    fn get_memory_usage(data: &ExecutionData) -> Option<MemoryUsage> {
         let status =
             fs::read_to_string(format!("/proc/{}/status", data.pid.expect("pid not set")))
             .expect("Failed to read /proc/<pid>/status");
-
-        if let Some(peak) =
+        let value = |name: &str| {
             status
-            .split("\n")
-            .find(|line| line.starts_with("VmPeak:"))
-        {
-            Some(
-                peak.split_whitespace()
-                    .nth(1)
-                    .expect("VmPeak value not found")
-                    .parse::<u64>()
-                    .expect("VmPeak value is not a number")
-            )
-        } else {
-            None
-        }
+                .split("\n")
+                .find(|line| line.starts_with(name))
+                .map(|line| {
+                    line.split_whitespace()
+                        .nth(1)
+                        .unwrap_or_else(|| panic!("{name} value not found"))
+                        .parse::<u64>()
+                        .unwrap_or_else(|_| panic!("{name} value is not a number"))
+                })
+        };
+
+        Some(MemoryUsage {
+            peak: value("VmPeak:")?,
+            stack: value("VmStk:")?,
+            data: value("VmData:")?,
+        })
     }
 }
\ No newline at end of file
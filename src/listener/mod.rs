@@ -8,6 +8,7 @@ pub(crate) mod perf;
 pub(crate) mod time;
 pub(crate) mod ptrace;
 pub(crate) mod memory;
+pub(crate) mod output;
 
 pub(crate) trait Listener: Debug {
     fn requires_timeout(&self, settings: &ExecutionSettings) -> bool;
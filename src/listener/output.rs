@@ -0,0 +1,309 @@
+use crate::listener::WakeupAction::Continue;
+use crate::listener::{Listener, WakeupAction};
+use crate::process::data::{ExecutionData, ExecutionSettings};
+use crate::process::output::CapturedOutput;
+use crate::process::{ExitStatus, OutputDigest, OutputPump};
+use crate::util::kill_pid;
+use cvt::cvt;
+use libc::{eventfd, pid_t, poll, pollfd, EFD_CLOEXEC, POLLIN};
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::sys::wait::WaitStatus;
+use nix::unistd::{dup2_stderr, dup2_stdout, pipe2};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+
+/// The size of the buffer the streams are read into.
+const BUFFER_SIZE: usize = 65536;
+
+/// Which output stream of the child a pipe replaces.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// A stream read by the pump thread, returned once it exits.
+struct FinishedStream {
+    stream: OutputStream,
+    digest: OutputDigest,
+    captured: Option<CapturedOutput>,
+}
+
+/// Reads the output streams of the child redirected to pipes by [`OutputPump`]s, hashing them,
+/// writing them to their tee files and enforcing their output limits.
+///
+/// The pipes are read on a separate thread, so the child never blocks on a full pipe while
+/// the supervisor is waiting for it.
+#[derive(Debug)]
+pub(crate) struct OutputListener {
+    pipes: Vec<OutputPipe>,
+    stop_event: Option<File>,
+    limit_exceeded: Arc<AtomicBool>,
+    thread: Option<JoinHandle<io::Result<Vec<FinishedStream>>>>,
+}
+
+#[derive(Debug)]
+struct OutputPipe {
+    stream: OutputStream,
+    /// The read end of the pipe, taken by the pump thread once it's started.
+    read: Option<File>,
+    /// The write end of the pipe, closed in the parent after the child is created.
+    write: Option<OwnedFd>,
+    pump: OutputPump,
+}
+
+impl OutputListener {
+    pub(crate) fn new(stdout_pump: Option<OutputPump>, stderr_pump: Option<OutputPump>) -> io::Result<OutputListener> {
+        let pipes = [(OutputStream::Stdout, stdout_pump), (OutputStream::Stderr, stderr_pump)]
+            .into_iter()
+            .filter_map(|(stream, pump)| pump.map(|pump| (stream, pump)))
+            .map(|(stream, pump)| {
+                let (read, write) = pipe2(OFlag::O_CLOEXEC)?;
+                // Only the supervisor's end is non-blocking, as the child expects its output to be blocking
+                fcntl(&read, FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+
+                Ok(OutputPipe {
+                    stream,
+                    read: Some(File::from(read)),
+                    write: Some(write),
+                    pump,
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(OutputListener {
+            pipes,
+            stop_event: None,
+            limit_exceeded: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        })
+    }
+
+    /// Stops reading the pipes, waiting for the pump thread to read what's left in them and exit.
+    fn stop(&mut self) -> Option<io::Result<Vec<FinishedStream>>> {
+        let thread = self.thread.take()?;
+        if let Some(stop_event) = self.stop_event.as_mut() {
+            stop_event.write_all(&1u64.to_ne_bytes()).expect("Failed to write to the stop event");
+        }
+
+        Some(thread.join().expect("The output pump thread panicked"))
+    }
+}
+
+impl Listener for OutputListener {
+    fn requires_timeout(&self, _: &ExecutionSettings) -> bool {
+        false
+    }
+
+    fn on_post_clone_child(&self, _: &ExecutionSettings, _: &ExecutionData) -> io::Result<()> {
+        // The original write ends are closed on execution, as they're created with O_CLOEXEC
+        for pipe in &self.pipes {
+            let write = pipe.write.as_ref().expect("output pipe closed before the child was created");
+            match pipe.stream {
+                OutputStream::Stdout => dup2_stdout(write)?,
+                OutputStream::Stderr => dup2_stderr(write)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn on_post_clone_parent(&mut self, _: &ExecutionSettings, data: &mut ExecutionData) -> io::Result<()> {
+        let child_pid = data.pid.expect("child pid not set");
+        let stop_event = unsafe { File::from_raw_fd(cvt(eventfd(0, EFD_CLOEXEC))?) };
+
+        let pump_loop = PumpLoop {
+            streams: self.pipes.iter_mut()
+                .map(|pipe| {
+                    pipe.write = None;
+                    PumpedStream {
+                        stream: pipe.stream,
+                        read: pipe.read.take(),
+                        limit_bytes: pipe.pump.limit_bytes,
+                        tee: pipe.pump.tee.take(),
+                        captured: pipe.pump.capture_limit_bytes.map(CapturedOutput::new),
+                        hasher: Sha256::new(),
+                        length: 0,
+                    }
+                })
+                .collect(),
+            stop_event: stop_event.try_clone()?,
+            child_pid,
+            limit_exceeded: self.limit_exceeded.clone(),
+        };
+        self.stop_event = Some(stop_event);
+        self.thread = Some(thread::spawn(move || {
+            let result = pump_loop.run();
+            // If the pump fails, the child could block on a full pipe forever, so it's killed instead
+            if result.is_err() {
+                let _ = kill_pid(child_pid);
+            }
+            result
+        }));
+
+        Ok(())
+    }
+
+    fn on_wakeup(&mut self, _: &ExecutionSettings, _: &mut ExecutionData) -> io::Result<WakeupAction> {
+        Ok(Continue)
+    }
+
+    fn on_execute_event(
+        &mut self,
+        _: &ExecutionSettings,
+        data: &mut ExecutionData,
+        status: &WaitStatus
+    ) -> io::Result<WakeupAction> {
+        // The exit status has to be set before the supervisor sets it based on the signal the pump killed the child with
+        if let WaitStatus::Exited(..) | WaitStatus::Signaled(..) = status
+            && self.limit_exceeded.load(Ordering::Acquire) {
+            data.execution_result.set_exit_status(ExitStatus::OLE("output limit exceeded".into()));
+        }
+
+        Ok(Continue)
+    }
+
+    fn on_post_execute(&mut self, _: &ExecutionSettings, data: &mut ExecutionData) -> io::Result<()> {
+        let Some(result) = self.stop() else {
+            return Ok(());
+        };
+
+        // The limit could have been exceeded while reading the data left in the pipes
+        if self.limit_exceeded.load(Ordering::Acquire) {
+            data.execution_result.set_exit_status(ExitStatus::OLE("output limit exceeded".into()));
+        }
+        for finished in result? {
+            match finished.stream {
+                OutputStream::Stdout => {
+                    data.execution_result.set_stdout_digest(finished.digest);
+                    data.captured_stdout = finished.captured;
+                }
+                OutputStream::Stderr => {
+                    data.execution_result.set_stderr_digest(finished.digest);
+                    data.captured_stderr = finished.captured;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for OutputListener {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+struct PumpedStream {
+    stream: OutputStream,
+    /// The read end of the pipe, or [`None`] once it has been closed by every writer.
+    read: Option<File>,
+    limit_bytes: Option<u64>,
+    tee: Option<File>,
+    captured: Option<CapturedOutput>,
+    hasher: Sha256,
+    length: u64,
+}
+
+impl PumpedStream {
+    /// Reads the data available in the pipe, returning whether the output limit was exceeded.
+    /// Unless `drain` is set, only a single read is made, so other streams aren't starved by a fast writer.
+    fn pump(&mut self, buffer: &mut [u8], drain: bool) -> io::Result<bool> {
+        while let Some(read) = self.read.as_mut() {
+            let size = match read.read(buffer) {
+                Ok(0) => {
+                    self.read = None;
+                    break;
+                }
+                Ok(size) => size,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            let allowed = self.limit_bytes.map_or(size, |limit| size.min((limit - self.length) as usize));
+            self.hasher.update(&buffer[..allowed]);
+            if let Some(tee) = self.tee.as_mut() {
+                tee.write_all(&buffer[..allowed])?;
+            }
+            if let Some(captured) = self.captured.as_mut() {
+                captured.push(&buffer[..allowed]);
+            }
+            self.length += allowed as u64;
+
+            if allowed < size {
+                return Ok(true);
+            }
+            if !drain {
+                break;
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn finish(self) -> FinishedStream {
+        FinishedStream {
+            stream: self.stream,
+            digest: OutputDigest {
+                length: self.length,
+                sha256: self.hasher.finalize().into(),
+            },
+            captured: self.captured,
+        }
+    }
+}
+
+struct PumpLoop {
+    streams: Vec<PumpedStream>,
+    stop_event: File,
+    child_pid: pid_t,
+    limit_exceeded: Arc<AtomicBool>,
+}
+
+impl PumpLoop {
+    fn run(mut self) -> io::Result<Vec<FinishedStream>> {
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+
+        loop {
+            let mut poll_fds: Vec<pollfd> = self.streams.iter()
+                .filter_map(|stream| stream.read.as_ref())
+                .chain([&self.stop_event])
+                .map(|file| pollfd { fd: file.as_raw_fd(), events: POLLIN, revents: 0 })
+                .collect();
+            match cvt(unsafe { poll(poll_fds.as_mut_ptr(), poll_fds.len() as _, -1) }) {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+
+            // Once the child has exited, only the data already written to the pipes is read, as processes
+            // it left running could otherwise keep the pipes open indefinitely
+            let stopped = poll_fds.last().is_some_and(|poll_fd| poll_fd.revents != 0);
+            for stream in &mut self.streams {
+                if stream.pump(&mut buffer, stopped)? {
+                    // The flag has to be set before the child is killed, so it's there once the child exits
+                    self.limit_exceeded.store(true, Ordering::Release);
+                    let _ = kill_pid(self.child_pid);
+                    return Ok(self.finish());
+                }
+            }
+
+            if stopped || self.streams.iter().all(|stream| stream.read.is_none()) {
+                return Ok(self.finish());
+            }
+        }
+    }
+
+    fn finish(self) -> Vec<FinishedStream> {
+        self.streams.into_iter().map(PumpedStream::finish).collect()
+    }
+}
@@ -98,11 +98,10 @@ impl Listener for PerfListener {
     fn on_post_execute(&mut self, settings: &ExecutionSettings, data: &mut ExecutionData) -> io::Result<()> {
         let instructions_used = self.get_instructions_used()?;
 
-        if let Some(instruction_limit) = settings.instruction_count_limit {
-            if instructions_used > instruction_limit {
-                data.execution_result
-                    .set_exit_status(ExitStatus::TLE("time limit exceeded".into()));
-            }
+        if let Some(instruction_limit) = settings.instruction_count_limit
+            && instructions_used > instruction_limit {
+            data.execution_result
+                .set_exit_status(ExitStatus::TLE("time limit exceeded".into()));
         }
 
         data.execution_result.set_instructions_used(instructions_used);
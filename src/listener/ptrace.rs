@@ -1,23 +1,81 @@
 use crate::listener::{Listener, WakeupAction};
 use crate::process::data::{ExecutionData, ExecutionSettings};
-use nix::sys::ptrace::{attach, cont, setoptions, Options};
+use crate::process::{ExitStatus, SyscallCount, SyscallViolation};
+use crate::seccomp::handler::SharedSyscallHandler;
+use crate::seccomp::notify::SeccompNotifier;
+use crate::seccomp::syscalls::{syscall_name, SyscallAbi};
+use crate::seccomp::SyscallDecision;
+use cvt::cvt;
+use libc::{c_int, c_long, pid_t, ptrace, ptrace_syscall_info, PTRACE_GET_SYSCALL_INFO, PTRACE_SYSCALL_INFO_ENTRY};
+use nix::sys::ptrace::{attach, cont, getevent, getsiginfo, setoptions, syscall, Event, Options};
+use nix::sys::signal::{kill, Signal};
 use nix::sys::wait::{waitpid, WaitStatus};
-use nix::unistd::Pid;
+use nix::unistd::{setpgid, Pid};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Formatter};
 use std::io;
+use std::os::fd::AsFd;
+use std::mem::{size_of, zeroed};
 use std::sync::LazyLock;
-use nix::sys::signal::kill;
 
 static PTRACE_OPTIONS: LazyLock<Options> = LazyLock::new(|| {
     Options::PTRACE_O_EXITKILL |
-    Options::PTRACE_O_TRACEEXIT
+    Options::PTRACE_O_TRACEEXIT |
+    Options::PTRACE_O_TRACEEXEC |
+    Options::PTRACE_O_TRACESECCOMP |
+    Options::PTRACE_O_TRACESYSGOOD
 });
 
-#[derive(Debug)]
-pub(crate) struct PtraceListener {}
+/// The options making every thread and process created by the child traced as well.
+static PTRACE_TASK_OPTIONS: LazyLock<Options> = LazyLock::new(|| {
+    Options::PTRACE_O_TRACECLONE |
+    Options::PTRACE_O_TRACEFORK |
+    Options::PTRACE_O_TRACEVFORK
+});
+
+pub(crate) struct PtraceListener {
+    initial_exec_done: bool,
+    syscall_handler: Option<SharedSyscallHandler>,
+    seccomp_notifier: Option<SeccompNotifier>,
+    /// The number of times each system call was made, if system calls are audited.
+    syscall_counts: Option<BTreeMap<(SyscallAbi, c_long), u64>>,
+    /// The running threads and processes created by the child, along with whether
+    /// they have been resumed from the stop they start in.
+    tasks: HashMap<pid_t, bool>,
+}
 
 impl PtraceListener {
-    pub(crate) fn new() -> PtraceListener {
-        PtraceListener {}
+    pub(crate) fn new(syscall_handler: Option<SharedSyscallHandler>, audit_syscalls: bool) -> PtraceListener {
+        PtraceListener {
+            initial_exec_done: false,
+            syscall_handler,
+            seccomp_notifier: None,
+            syscall_counts: audit_syscalls.then(BTreeMap::new),
+            tasks: HashMap::new(),
+        }
+    }
+
+    /// Resumes the stopped child, stopping it again at the next system call if system calls are audited.
+    fn resume(&self, pid: Pid, signal: Option<Signal>) -> io::Result<()> {
+        if self.syscall_counts.is_some() {
+            syscall(pid, signal)?;
+        } else {
+            cont(pid, signal)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Debug for PtraceListener {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PtraceListener")
+            .field("initial_exec_done", &self.initial_exec_done)
+            .field("has_syscall_handler", &self.syscall_handler.is_some())
+            .field("seccomp_notifier", &self.seccomp_notifier)
+            .field("syscall_counts", &self.syscall_counts)
+            .field("tasks", &self.tasks)
+            .finish()
     }
 }
 
@@ -26,17 +84,27 @@ impl Listener for PtraceListener {
         false
     }
 
-    fn on_post_clone_child(&self, _: &ExecutionSettings, _: &ExecutionData) -> io::Result<()> {
+    fn on_post_clone_child(&self, settings: &ExecutionSettings, _: &ExecutionData) -> io::Result<()> {
+        // The supervisor waits for all the traced threads and processes using the process group of the child
+        if settings.trace_all_tasks {
+            setpgid(Pid::from_raw(0), Pid::from_raw(0))?;
+        }
+
         Ok(())
     }
 
-    fn on_post_clone_parent(&mut self, _: &ExecutionSettings, data: &mut ExecutionData) -> io::Result<()> {
+    fn on_post_clone_parent(&mut self, settings: &ExecutionSettings, data: &mut ExecutionData) -> io::Result<()> {
         let root_pid = data.pid.expect("child pid not set");
+        let options = if settings.trace_all_tasks {
+            *PTRACE_OPTIONS | *PTRACE_TASK_OPTIONS
+        } else {
+            *PTRACE_OPTIONS
+        };
 
         attach(Pid::from_raw(root_pid))?;
         waitpid(Pid::from_raw(root_pid), None)?;
-        setoptions(Pid::from_raw(root_pid), *PTRACE_OPTIONS)?;
-        cont(Pid::from_raw(root_pid), None)?;
+        setoptions(Pid::from_raw(root_pid), options)?;
+        self.resume(Pid::from_raw(root_pid), None)?;
 
         Ok(())
     }
@@ -47,19 +115,205 @@ impl Listener for PtraceListener {
 
     fn on_execute_event(
         &mut self,
-        _: &ExecutionSettings,
+        settings: &ExecutionSettings,
         data: &mut ExecutionData,
         status: &WaitStatus
     ) -> io::Result<WakeupAction> {
-        if let WaitStatus::PtraceEvent(_, _, _) = status
-            && kill(Pid::from_raw(data.pid.expect("pid should not be None")), None).is_ok() {
-            cont(Pid::from_raw(data.pid.expect("pid should not be None")), None)?
+        if let WaitStatus::Exited(pid, _) | WaitStatus::Signaled(pid, _, _) = *status {
+            self.tasks.remove(&pid.as_raw());
+            // The child could have been killed by the seccomp notifier for a forbidden system call
+            if let Some(violation) = self.seccomp_notifier.as_ref().and_then(SeccompNotifier::take_violation) {
+                data.execution_result.set_syscall_violation(violation);
+            }
+        }
+
+        if let WaitStatus::PtraceSyscall(pid) = *status {
+            return self.on_syscall_stop(pid)
+        }
+        let WaitStatus::PtraceEvent(pid, signal, event) = *status else {
+            return Ok(WakeupAction::Continue)
+        };
+        if kill(pid, None).is_err() {
+            return Ok(WakeupAction::Continue)
+        }
+
+        match event {
+            event if event == Event::PTRACE_EVENT_SECCOMP as c_int => {
+                return self.on_seccomp_stop(settings, data, pid)
+            }
+            event if event == Event::PTRACE_EVENT_EXEC as c_int => {
+                self.initial_exec_done = true;
+                // A thread executing a program takes over the thread id of the main thread of its process
+                let former_tid = getevent(pid)? as pid_t;
+                if former_tid != pid.as_raw() {
+                    self.tasks.remove(&former_tid);
+                }
+                self.resume(pid, None)?
+            }
+            event if event == Event::PTRACE_EVENT_CLONE as c_int ||
+                event == Event::PTRACE_EVENT_FORK as c_int ||
+                event == Event::PTRACE_EVENT_VFORK as c_int => {
+                return self.on_task_created(settings, data, pid)
+            }
+            // The stop a new thread or process starts in, in which case the SIGSTOP causing it
+            // isn't passed on, as it was sent by the kernel
+            0 if signal == Signal::SIGSTOP && self.tasks.get(&pid.as_raw()) != Some(&true)
+                && pid.as_raw() != data.pid.expect("child pid not set") => {
+                self.tasks.insert(pid.as_raw(), true);
+                self.resume(pid, None)?
+            }
+            // A signal-delivery-stop, in which case the signal has to be passed on to the child,
+            // or a group-stop, for which PTRACE_GETSIGINFO fails and the child is simply continued
+            0 => self.resume(pid, getsiginfo(pid).ok().map(|_| signal))?,
+            _ => self.resume(pid, None)?,
         }
 
         Ok(WakeupAction::Continue)
     }
 
-    fn on_post_execute(&mut self, _: &ExecutionSettings, _: &mut ExecutionData) -> io::Result<()> {
+    fn on_post_execute(&mut self, _: &ExecutionSettings, data: &mut ExecutionData) -> io::Result<()> {
+        if let Some(mut seccomp_notifier) = self.seccomp_notifier.take() {
+            seccomp_notifier.stop();
+        }
+        if let Some(syscall_counts) = self.syscall_counts.as_ref() {
+            data.execution_result.set_syscall_counts(
+                syscall_counts.iter()
+                    .map(|(&(abi, number), &count)| SyscallCount { abi, number, name: syscall_name(abi, number), count })
+                    .collect()
+            );
+        }
+
         Ok(())
     }
 }
+
+impl PtraceListener {
+    /// Handles the stop of the child after it has created a new thread or process.
+    fn on_task_created(
+        &mut self,
+        settings: &ExecutionSettings,
+        data: &mut ExecutionData,
+        pid: Pid
+    ) -> io::Result<WakeupAction> {
+        // The new task could have already been reported in the stop it starts in
+        let new_tid = getevent(pid)? as pid_t;
+        self.tasks.entry(new_tid).or_insert(false);
+
+        if let Some(thread_limit) = settings.thread_limit && self.tasks.len() > thread_limit as usize {
+            data.execution_result.set_exit_status(ExitStatus::RV("thread limit exceeded".into()));
+            return Ok(WakeupAction::Kill);
+        }
+
+        self.resume(pid, None)?;
+        Ok(WakeupAction::Continue)
+    }
+
+    /// Handles a syscall-enter-stop or a syscall-exit-stop, which only happen if system calls are audited.
+    fn on_syscall_stop(&mut self, pid: Pid) -> io::Result<WakeupAction> {
+        let syscall_info = get_syscall_info(pid)?;
+        // System calls made before the execution of the child program are made by perfjail itself
+        if syscall_info.op == PTRACE_SYSCALL_INFO_ENTRY && self.initial_exec_done
+            && let Some(syscall_counts) = self.syscall_counts.as_mut() {
+            let syscall = unsafe { syscall_info.u.entry.nr } as c_long;
+            if let Some(abi) = SyscallAbi::from_audit_arch(syscall_info.arch, syscall) {
+                *syscall_counts.entry((abi, syscall)).or_default() += 1;
+            }
+        }
+
+        self.resume(pid, None)?;
+        Ok(WakeupAction::Continue)
+    }
+
+    /// Handles a seccomp stop, which happens when the child calls `execve` or `execveat`.
+    fn on_seccomp_stop(
+        &mut self,
+        settings: &ExecutionSettings,
+        data: &mut ExecutionData,
+        pid: Pid
+    ) -> io::Result<WakeupAction> {
+        let policy = settings.syscall_policy.as_ref()
+            .expect("seccomp stops should only happen when a syscall policy is set");
+        let syscall_info = get_syscall_info(pid)?;
+        let seccomp_info = unsafe { syscall_info.u.seccomp };
+        let syscall = seccomp_info.nr as c_long;
+        let abi = SyscallAbi::from_audit_arch(syscall_info.arch, syscall);
+        let child_pid = data.pid.expect("child pid not set");
+
+        let (decision, rule) = if self.initial_exec_done {
+            let (action, rule) = policy.evaluate(abi, syscall, &seccomp_info.args, child_pid);
+            let decision = SyscallDecision::from_action(action, self.syscall_handler.as_ref(), syscall, &seccomp_info.args, pid.as_raw());
+            (decision, rule)
+        } else {
+            // The initial execution of the child program is always allowed, but the notifications
+            // of the filter have to be handled before it happens, as the listener is closed on execution
+            if self.seccomp_notifier.is_none() {
+                self.seccomp_notifier = Some(SeccompNotifier::start(
+                    data.pid_fd.as_ref().expect("child pid_fd not set").as_fd(),
+                    data.raw_seccomp_listener_fd,
+                    child_pid,
+                    policy.clone(),
+                    self.syscall_handler.clone(),
+                )?);
+            }
+            (SyscallDecision::ALLOW, None)
+        };
+
+        match decision {
+            SyscallDecision::ALLOW => {
+                self.resume(pid, None)?;
+                Ok(WakeupAction::Continue)
+            }
+            SyscallDecision::ERRNO(errno) => {
+                skip_syscall(pid, -(errno as i64))?;
+                self.resume(pid, None)?;
+                Ok(WakeupAction::Continue)
+            }
+            SyscallDecision::KILL => {
+                data.execution_result.set_syscall_violation(SyscallViolation::new(
+                    abi,
+                    syscall,
+                    seccomp_info.args,
+                    pid.as_raw(),
+                    rule.map(ToString::to_string),
+                ));
+                Ok(WakeupAction::Kill)
+            }
+        }
+    }
+}
+
+/// Returns information about the system call the child is stopped at.
+///
+/// This doesn't use [`nix::sys::ptrace::syscall_info`], as it passes a buffer size of zero to
+/// the kernel, which makes it leave the returned structure empty.
+fn get_syscall_info(pid: Pid) -> io::Result<ptrace_syscall_info> {
+    unsafe {
+        let mut info: ptrace_syscall_info = zeroed();
+        cvt(ptrace(
+            PTRACE_GET_SYSCALL_INFO,
+            pid.as_raw(),
+            size_of::<ptrace_syscall_info>(),
+            &mut info as *mut ptrace_syscall_info,
+        ))?;
+
+        Ok(info)
+    }
+}
+
+/// Makes the system call the child is stopped at get skipped, returning `return_value` instead.
+#[cfg(target_arch = "x86_64")]
+fn skip_syscall(pid: Pid, return_value: i64) -> io::Result<()> {
+    use nix::sys::ptrace::{getregs, setregs};
+
+    let mut regs = getregs(pid)?;
+    regs.orig_rax = u64::MAX;
+    regs.rax = return_value as u64;
+    setregs(pid, regs)?;
+
+    Ok(())
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn skip_syscall(_: Pid, _: i64) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
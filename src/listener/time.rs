@@ -6,6 +6,7 @@ use libc::{sysconf, _SC_CLK_TCK};
 use std::sync::OnceLock;
 use std::time::{Duration, Instant};
 use std::{fs, io};
+use nix::sys::signal::Signal;
 use nix::sys::wait::WaitStatus;
 
 static CLOCK_TICKS_PER_SECOND: OnceLock<u64> = OnceLock::new();
@@ -74,10 +75,21 @@ impl Listener for TimeListener {
 
     fn on_execute_event(
         &mut self,
-        _: &ExecutionSettings,
-        _: &mut ExecutionData,
-        _: &WaitStatus
+        settings: &ExecutionSettings,
+        data: &mut ExecutionData,
+        status: &WaitStatus
     ) -> io::Result<WakeupAction> {
+        // A child killed by the kernel for exceeding the CPU time backstop has to be told apart from one killed
+        // for other reasons before the supervisor sets the exit status based on the signal
+        if let WaitStatus::Signaled(pid, Signal::SIGKILL, _) = *status
+            && pid.as_raw() == data.pid.expect("pid not set")
+            && let Some(cpu_time_backstop) = settings.cpu_time_backstop {
+            let process_time_usage = self.get_process_time_usage(data)?;
+            if process_time_usage.user_time + process_time_usage.system_time >= cpu_time_backstop {
+                data.execution_result.set_exit_status(ExitStatus::TLE("time limit exceeded".into()));
+            }
+        }
+
         Ok(WakeupAction::Continue)
     }
 
@@ -2,15 +2,23 @@ use crate::listener::WakeupAction;
 use crate::process::child::ChildState::{Reapable, Reaped};
 use crate::process::data::ExecutionContext;
 use crate::process::execution_result::{ExecutionResult, ExitReason};
+use crate::process::output::Output;
 use crate::process::timeout::{add_timeout_thread, remove_timeout_thread};
-use crate::util::{kill_pid, CHILD_STACK_SIZE};
+use crate::util::{kill_pid, PinnedThread, CHILD_STACK_SIZE};
 use cvt::{cvt, cvt_r};
-use libc::{clone, id_t, pid_t, waitpid, CLONE_PIDFD, CLONE_VFORK, CLONE_VM, SIGCHLD, WNOHANG};
+use libc::{
+    clone, dup2, execve, fcntl, id_t, pid_t, setpriority, syscall, waitpid, SYS_close_range, CLONE_PIDFD, CLONE_VFORK,
+    CLONE_VM, CLOSE_RANGE_CLOEXEC, EINVAL, ENOSYS, FD_CLOEXEC, F_DUPFD_CLOEXEC, F_GETFD, F_SETFD, PRIO_PROCESS,
+    SIGCHLD, WNOHANG,
+};
 use nix::errno::Errno;
+use nix::sched::sched_setaffinity;
+use nix::sys::resource::{getrlimit, setrlimit, Resource};
 use nix::sys::wait::{Id, WaitPidFlag, WaitStatus};
-use nix::unistd::{chdir, close, dup2_stderr, dup2_stdin, dup2_stdout, execvp, Pid};
-use std::ffi::{c_int, c_void};
+use nix::unistd::{chdir, close, dup2_stderr, dup2_stdin, dup2_stdout, Pid};
+use std::ffi::{c_int, c_uint, c_void};
 use std::io;
+use std::io::{PipeReader, PipeWriter};
 use std::os::fd::AsRawFd;
 use std::ptr::null_mut;
 use std::sync::{Mutex, Once};
@@ -48,25 +56,51 @@ enum ChildState {
 ///
 /// assert!(matches!(result.exit_reason, Exited { exit_status: 0 }));
 /// ```
-pub struct JailedChild<'a> {
-    child_internals: Mutex<ChildInternals<'a>>,
+pub struct JailedChild {
+    /// The handle for writing to the child’s standard input (stdin), if it has been captured
+    /// with [`Stdio::Piped`](crate::process::Stdio::Piped).
+    ///
+    /// As [`run`](JailedChild::run) blocks until the child exits, the handle has to be written to
+    /// (and dropped, so the child sees the end of its input) from another thread.
+    pub stdin: Option<PipeWriter>,
+    /// The handle for reading from the child’s standard output (stdout), if it has been captured
+    /// with [`Stdio::Piped`](crate::process::Stdio::Piped).
+    ///
+    /// As [`run`](JailedChild::run) blocks until the child exits, the handle has to be read from
+    /// another thread, or the child can block on a full pipe forever.
+    pub stdout: Option<PipeReader>,
+    /// The handle for reading from the child’s standard error (stderr), if it has been captured
+    /// with [`Stdio::Piped`](crate::process::Stdio::Piped).
+    ///
+    /// As [`run`](JailedChild::run) blocks until the child exits, the handle has to be read from
+    /// another thread, or the child can block on a full pipe forever.
+    pub stderr: Option<PipeReader>,
+    child_internals: Mutex<ChildInternals>,
     child_state: Mutex<ChildState>,
     run_once: Once
 }
 
-struct ChildInternals<'a> {
-    context: Box<ExecutionContext<'a>>,
+struct ChildInternals {
+    context: Box<ExecutionContext>,
     run_error: Option<io::Error>,
 }
 
-unsafe impl Sync for JailedChild<'_> {}
-unsafe impl Send for JailedChild<'_> {}
+unsafe impl Sync for JailedChild {}
+unsafe impl Send for JailedChild {}
 
-impl JailedChild<'_> {
-    pub(crate) fn new(context: Box<ExecutionContext>) -> JailedChild {
+impl JailedChild {
+    pub(crate) fn new(
+        context: Box<ExecutionContext>,
+        stdin: Option<PipeWriter>,
+        stdout: Option<PipeReader>,
+        stderr: Option<PipeReader>,
+    ) -> JailedChild {
         let pid = context.data.pid.expect("pid not set");
 
         JailedChild {
+            stdin,
+            stdout,
+            stderr,
             child_internals: Mutex::new(ChildInternals { context, run_error: None }),
             child_state: Mutex::new(Reapable { pid }),
             run_once: Once::new(),
@@ -97,13 +131,50 @@ impl JailedChild<'_> {
             .expect("Failed to lock child_internals");
         self.run_once.call_once(|| child_internals.run_saving_result(&self.child_state));
 
-        if let Some(e) = (&mut child_internals).run_error.take() {
+        if let Some(e) = child_internals.run_error.take() {
             Err(e)
         } else {
             Ok(child_internals.context.data.execution_result.clone())
         }
     }
 
+    /// Runs the child process like [`run`](JailedChild::run), returning the result together with the
+    /// standard output and standard error captured by [`OutputPump`](crate::process::OutputPump)s
+    /// with [`capture`](crate::process::OutputPump::capture) set.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::{OutputPump, Perfjail};
+    ///
+    /// let output = Perfjail::new("echo")
+    ///     .arg("hello")
+    ///     .stdout_pump(OutputPump::new().capture(1024))
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run_with_output()
+    ///     .expect("failed to run echo");
+    ///
+    /// assert_eq!(output.stdout, b"hello\n");
+    /// ```
+    pub fn run_with_output(self) -> io::Result<Output> {
+        let result = self.run()?;
+        let mut child_internals = self.child_internals.lock()
+            .expect("Failed to lock child_internals");
+        let stdout = child_internals.context.data.captured_stdout.take().unwrap_or_default();
+        let stderr = child_internals.context.data.captured_stderr.take().unwrap_or_default();
+
+        Ok(Output {
+            result,
+            stdout: stdout.bytes,
+            stderr: stderr.bytes,
+            stdout_truncated: stdout.truncated,
+            stderr_truncated: stderr.truncated,
+        })
+    }
+
     /// Forces the child process to exit. If the child has already exited, `Ok(())` is returned.
     ///
     /// This is equivalent to sending a SIGKILL signal.
@@ -134,14 +205,14 @@ impl JailedChild<'_> {
     }
 }
 
-impl ChildInternals<'_> {
+impl ChildInternals {
     pub(crate) fn run_saving_result(&mut self, child_state: &Mutex<ChildState>) {
         if let Err(e) = self.run() {
             _ = self.run_error.insert(e);
         }
 
         unsafe {
-            kill_pid(self.context.data.pid.expect("pid not set")).expect("Failed to kill child process");
+            self.kill_child().expect("Failed to kill child process");
 
             let mut child_state = child_state.lock().expect("Failed to lock pid_valid");
             *child_state = Reaped;
@@ -157,6 +228,9 @@ impl ChildInternals<'_> {
 
     fn run(&mut self) -> io::Result<()> {
         self.propagate_child_error()?;
+        let _pinned_supervisor = self.context.settings.supervisor_cpu_affinity.as_ref()
+            .map(PinnedThread::pin)
+            .transpose()?;
         let mut requires_timeout = false;
         for listener in &mut self.context.listeners {
             listener.on_post_clone_parent(&self.context.settings, &mut self.context.data)?;
@@ -167,6 +241,9 @@ impl ChildInternals<'_> {
         }
         self.context.data.parent_ready_barrier.wait();
 
+        let root_pid = Pid::from_raw(self.context.data.pid.unwrap());
+        let trace_all_tasks = self.context.settings.trace_all_tasks;
+
         loop {
             let mut action = WakeupAction::Continue;
             for listener in &mut self.context.listeners {
@@ -176,12 +253,18 @@ impl ChildInternals<'_> {
             }
 
             if action == WakeupAction::Kill {
-                kill_pid(self.context.data.pid.unwrap())?
+                self.kill_child()?
             }
 
+            // Threads and processes created by the child are only reported if they're traced
+            let (wait_id, wait_flags) = if trace_all_tasks {
+                (Id::PGid(root_pid), WaitPidFlag::__WALL)
+            } else {
+                (Id::Pid(root_pid), WaitPidFlag::empty())
+            };
             let wait_info = match nix::sys::wait::waitid(
-                Id::Pid(Pid::from_raw(self.context.data.pid.unwrap())),
-                WaitPidFlag::WEXITED | WaitPidFlag::WSTOPPED | WaitPidFlag::WNOWAIT
+                wait_id,
+                WaitPidFlag::WEXITED | WaitPidFlag::WSTOPPED | WaitPidFlag::WNOWAIT | wait_flags
             )  {
                 Ok(r) => r,
                 Err(Errno::EINTR) => continue,
@@ -190,18 +273,31 @@ impl ChildInternals<'_> {
 
             self.propagate_child_error()?;
 
+            let mut action = WakeupAction::Continue;
             for listener in &mut self.context.listeners {
-                listener.on_execute_event(&self.context.settings, &mut self.context.data, &wait_info)?;
+                action = action.combine(
+                    listener.on_execute_event(&self.context.settings, &mut self.context.data, &wait_info)?
+                );
+            }
+
+            if action == WakeupAction::Kill {
+                self.kill_child()?
             }
-            
+
+
             match wait_info {
+                WaitStatus::Exited(pid, _) | WaitStatus::Signaled(pid, _, _) if pid != root_pid => {
+                    // Another thread or process of the child has exited, so it only has to be reaped
+                    nix::sys::wait::waitpid(pid, Some(WaitPidFlag::__WALL))?;
+                    continue;
+                }
                 WaitStatus::Exited(_, status) => {
                     self.context
                         .data
                         .execution_result
                         .set_exit_reason(ExitReason::Exited {
                             exit_status: status,
-                        });
+                        }, &self.context.settings);
 
                     break;
                 }
@@ -211,7 +307,7 @@ impl ChildInternals<'_> {
                         .execution_result
                         .set_exit_reason(ExitReason::Killed {
                             signal: signal as i32,
-                        });
+                        }, &self.context.settings);
 
                     break;
                 }
@@ -235,6 +331,17 @@ impl ChildInternals<'_> {
         Ok(())
     }
 
+    /// Kills the child, along with every process it has created if they're traced.
+    fn kill_child(&self) -> io::Result<()> {
+        let pid = self.context.data.pid.expect("pid not set");
+        if self.context.settings.trace_all_tasks {
+            // The child is the leader of its own process group
+            kill_pid(-pid)
+        } else {
+            kill_pid(pid)
+        }
+    }
+
     fn propagate_child_error(&mut self) -> io::Result<()> {
         if let Some(e) = self.context.data.child_error.take() {
             Err(e)
@@ -284,24 +391,106 @@ fn execute_child_impl(context: &mut ExecutionContext) -> io::Result<()> {
         chdir(working_dir)?;
     }
 
-    if let Some(stdin_fd) = context.settings.stdin_fd.as_ref() {
+    let [stdin_fd, stdout_fd, stderr_fd] = &context.data.stdio_fds;
+    if let Some(stdin_fd) = stdin_fd {
         dup2_stdin(stdin_fd)?;
         close(stdin_fd.as_raw_fd())?;
     }
-    if let Some(stdout_fd) = context.settings.stdout_fd.as_ref() {
+    if let Some(stdout_fd) = stdout_fd {
         dup2_stdout(stdout_fd)?;
         close(stdout_fd.as_raw_fd())?;
     }
-    if let Some(stderr_fd) = context.settings.stderr_fd.as_ref() {
+    if let Some(stderr_fd) = stderr_fd {
         dup2_stderr(stderr_fd)?;
         close(stderr_fd.as_raw_fd())?;
     }
 
+    if context.settings.close_inherited_fds {
+        mark_inherited_fds_cloexec()?;
+    }
+
     context.data.child_ready_barrier.wait();
     context.data.parent_ready_barrier.wait();
 
-    execvp(&context.settings.executable_path, &context.settings.args)?;
+    // Failing before the barriers would leave the parent waiting for the child forever,
+    // so the mapped descriptors, the limits and the scheduling settings, which can be invalid,
+    // are only set after them. The descriptors are mapped first, as the limits could lower RLIMIT_NOFILE
+    for (child_fd, parent_fd) in &context.settings.fd_mappings {
+        map_fd(parent_fd.as_raw_fd(), *child_fd)?;
+    }
+
+    if let Some(output_limit) = context.settings.output_limit_bytes {
+        setrlimit(Resource::RLIMIT_FSIZE, output_limit, output_limit)?;
+    }
+    // The limits are set after the listeners have run, as the memory listener raises the stack limit
+    if let Some(stack_limit) = context.settings.stack_limit_kibibytes {
+        setrlimit(Resource::RLIMIT_STACK, stack_limit * 1024, stack_limit * 1024)?;
+    }
+    if let Some(cpu_time_backstop) = context.settings.cpu_time_backstop {
+        let soft_limit = cpu_time_backstop.as_secs();
+        setrlimit(Resource::RLIMIT_CPU, soft_limit, soft_limit + 1)?;
+    }
+    // The limits set by the user take priority over all the others, so they're set last
+    for (&resource, &(soft, hard)) in &context.settings.rlimits {
+        setrlimit(resource, soft, hard)?;
+    }
+
+    if let Some(cpu_affinity) = context.settings.cpu_affinity.as_ref() {
+        sched_setaffinity(Pid::from_raw(0), cpu_affinity)?;
+    }
+    if let Some(scheduling_policy) = context.settings.scheduling_policy {
+        scheduling_policy.apply()?;
+    }
+    if let Some(nice) = context.settings.nice {
+        cvt(unsafe { setpriority(PRIO_PROCESS, 0, nice) })?;
+    }
+    if let Some(io_priority) = context.settings.io_priority {
+        io_priority.apply()?;
+    }
+
+    if let Some(seccomp_filter) = context.data.seccomp_filter.as_ref() {
+        context.data.raw_seccomp_listener_fd = seccomp_filter.install()?;
+    }
+
+    // The child can't make any other system call between installing the filter and executing the program,
+    // so it calls execve directly with the arguments and the environment prepared by the parent
+    let settings = &context.settings;
+    unsafe { execve(settings.executable_path.as_ptr(), settings.argv.as_ptr(), settings.envp.as_ptr()) };
+
+    // Execve returns only if it has failed, in which case the function returns the appropriate result
+    Err(io::Error::last_os_error())
+}
+
+/// Duplicates `parent_fd` onto `child_fd`. If `child_fd` is already open, it's moved to a free
+/// descriptor first, as it can be one perfjail itself uses until the program is executed.
+fn map_fd(parent_fd: c_int, child_fd: c_int) -> io::Result<()> {
+    if unsafe { fcntl(child_fd, F_GETFD) } != -1 {
+        cvt(unsafe { fcntl(child_fd, F_DUPFD_CLOEXEC, child_fd) })?;
+    }
+    cvt_r(|| unsafe { dup2(parent_fd, child_fd) })?;
+
+    Ok(())
+}
+
+/// Makes every file descriptor of the child besides its standard input, output and error get closed
+/// once the program is executed, so it doesn't inherit the ones the parent opened without `O_CLOEXEC`.
+///
+/// The descriptors aren't closed right away, as perfjail itself uses some of them until the program
+/// is executed (for example, the pipe of the memory listener).
+fn mark_inherited_fds_cloexec() -> io::Result<()> {
+    match cvt(unsafe { syscall(SYS_close_range, 3 as c_uint, c_uint::MAX, CLOSE_RANGE_CLOEXEC) }) {
+        Ok(_) => return Ok(()),
+        // CLOSE_RANGE_CLOEXEC is only supported since Linux 5.11, and close_range since Linux 5.9
+        Err(e) if e.raw_os_error() == Some(EINVAL) || e.raw_os_error() == Some(ENOSYS) => {}
+        Err(e) => return Err(e),
+    }
+
+    // Older kernels need every descriptor to be marked separately, which can't allocate
+    // memory, as the child shares it with the parent
+    let (max_fds, _) = getrlimit(Resource::RLIMIT_NOFILE)?;
+    for fd in 3..max_fds.min(c_int::MAX as u64) as c_int {
+        unsafe { fcntl(fd, F_SETFD, FD_CLOEXEC) };
+    }
 
-    // Execv returns only if it has failed, in which case the function returns the appropriate result
-    unreachable!();
+    Ok(())
 }
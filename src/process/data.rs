@@ -1,36 +1,75 @@
 use crate::listener::Listener;
 use crate::process::execution_result::ExecutionResult;
-use crate::process::jail::Perfjail;
-use crate::util::CHILD_STACK_SIZE;
-use std::ffi::{c_int, CString};
+use crate::process::output::CapturedOutput;
+use crate::process::jail::{Feature, Perfjail};
+use crate::process::{IoPriority, SchedulingPolicy};
+use crate::seccomp::filter::SeccompFilter;
+use crate::seccomp::SyscallPolicy;
+use crate::util::{TempDir, CHILD_STACK_SIZE};
+use linear_map::LinearMap;
+use nix::fcntl::{fcntl, FcntlArg};
+use nix::sched::CpuSet;
+use nix::sys::resource::Resource;
+use nix::unistd::{access, AccessFlags};
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::{c_char, c_int, CString, OsStr, OsString};
+use std::iter;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::io;
-use std::os::fd::{BorrowedFd, OwnedFd};
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
 use std::path::PathBuf;
+use std::ptr::null;
 use std::sync::Barrier;
 use std::time::Duration;
 
 #[derive(Debug)]
-pub(crate) struct ExecutionContext<'a> {
-    pub(crate) settings: ExecutionSettings<'a>,
+pub(crate) struct ExecutionContext {
+    pub(crate) settings: ExecutionSettings,
     pub(crate) data: ExecutionData,
     pub(crate) listeners: Vec<Box<dyn Listener>>,
 }
 
 #[readonly::make]
 #[derive(Debug)]
-pub(crate) struct ExecutionSettings<'a> {
+pub(crate) struct ExecutionSettings {
     pub(crate) real_time_limit: Option<Duration>,
     pub(crate) user_time_limit: Option<Duration>,
     pub(crate) system_time_limit: Option<Duration>,
     pub(crate) user_system_time_limit: Option<Duration>,
+    /// The CPU time after which the kernel sends `SIGXCPU` to the child through `RLIMIT_CPU`,
+    /// followed by `SIGKILL` a second later.
+    pub(crate) cpu_time_backstop: Option<Duration>,
     pub(crate) instruction_count_limit: Option<i64>,
     pub(crate) memory_limit_kibibytes: Option<u64>,
+    pub(crate) stack_limit_kibibytes: Option<u64>,
+    /// The resource limits set by the user, as `(soft, hard)` pairs.
+    pub(crate) rlimits: LinearMap<Resource, (u64, u64)>,
+    pub(crate) cpu_affinity: Option<CpuSet>,
+    pub(crate) supervisor_cpu_affinity: Option<CpuSet>,
+    pub(crate) nice: Option<i32>,
+    pub(crate) scheduling_policy: Option<SchedulingPolicy>,
+    pub(crate) io_priority: Option<IoPriority>,
+    pub(crate) output_limit_bytes: Option<u64>,
+    pub(crate) thread_limit: Option<u32>,
+    /// Whether every thread and process of the child is traced, in which case the child is run
+    /// in its own process group, which its seccomp filter keeps it from leaving.
+    pub(crate) trace_all_tasks: bool,
+    /// The path of the program, already searched for in the `PATH` of the parent.
     pub(crate) executable_path: CString,
     pub(crate) args: Vec<CString>,
     pub(crate) working_dir: Option<PathBuf>,
-    pub(crate) stdin_fd: Option<BorrowedFd<'a>>,
-    pub(crate) stdout_fd: Option<BorrowedFd<'a>>,
-    pub(crate) stderr_fd: Option<BorrowedFd<'a>>,
+    pub(crate) env: Vec<CString>,
+    /// The null-terminated arrays of pointers to `args` and `env` passed to `execve`. They're prepared
+    /// in advance, as once its seccomp filter is installed, the child can't make any system call other
+    /// than `execve` (like the ones `malloc` can make) before the supervisor starts handling its notifications.
+    pub(crate) argv: Vec<*const c_char>,
+    pub(crate) envp: Vec<*const c_char>,
+    pub(crate) close_inherited_fds: bool,
+    /// The file descriptors mapped into the child, as pairs of their number in the child and a duplicate
+    /// of the parent's descriptor above every such number, so moving one into place can't overwrite another.
+    pub(crate) fd_mappings: Vec<(RawFd, OwnedFd)>,
+    pub(crate) syscall_policy: Option<SyscallPolicy>,
 }
 
 #[derive(Debug)]
@@ -38,39 +77,92 @@ pub(crate) struct ExecutionData {
     pub(crate) pid_fd: Option<OwnedFd>,
     pub(crate) raw_pid_fd: c_int,
     pub(crate) pid: Option<c_int>,
+    pub(crate) seccomp_filter: Option<SeccompFilter>,
+    /// The directory set as the home directory of the child, removed once the child is dropped.
+    #[allow(dead_code)]
+    pub(crate) home_dir: Option<TempDir>,
+    pub(crate) raw_seccomp_listener_fd: c_int,
+    /// The file descriptors the stdin, stdout and stderr of the child are connected to, or [`None`] for the
+    /// inherited ones. They're closed in the parent once the child has moved them into place.
+    pub(crate) stdio_fds: [Option<OwnedFd>; 3],
     pub(crate) execution_result: ExecutionResult,
+    /// The beginning of the standard output of the child, if it's captured by an output pump.
+    pub(crate) captured_stdout: Option<CapturedOutput>,
+    /// The beginning of the standard error of the child, if it's captured by an output pump.
+    pub(crate) captured_stderr: Option<CapturedOutput>,
     pub(crate) child_error: Option<io::Error>,
     pub(crate) child_stack: [u8; CHILD_STACK_SIZE],
     pub(crate) child_ready_barrier: Barrier,
     pub(crate) parent_ready_barrier: Barrier,
 }
 
-impl ExecutionSettings<'_> {
-    pub(crate) fn new(executor: Perfjail) -> ExecutionSettings {
-        ExecutionSettings {
+impl ExecutionSettings {
+    pub(crate) fn new(executor: Perfjail) -> io::Result<ExecutionSettings> {
+        let first_free_fd = executor.fd_mappings.keys().next_back().map_or(0, |&child_fd| child_fd + 1);
+        let fd_mappings = executor.fd_mappings.iter()
+            .map(|(&child_fd, parent_fd)| {
+                let fd = fcntl(parent_fd, FcntlArg::F_DUPFD_CLOEXEC(first_free_fd))?;
+                Ok((child_fd, unsafe { OwnedFd::from_raw_fd(fd) }))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let env = child_env(executor.env_clear, executor.env_vars)?;
+        let argv = null_terminated(&executor.args);
+        let envp = null_terminated(&env);
+
+        Ok(ExecutionSettings {
             real_time_limit: executor.real_time_limit,
             user_time_limit: executor.user_time_limit,
             system_time_limit: executor.system_time_limit,
             user_system_time_limit: executor.user_system_time_limit,
+            cpu_time_backstop: executor.cpu_time_backstop_margin.and_then(|margin| {
+                let limit = executor.user_system_time_limit.or(executor.user_time_limit)?;
+                // RLIMIT_CPU is set in whole seconds
+                Some(Duration::from_secs((limit + margin).as_secs_f64().ceil() as u64))
+            }),
             instruction_count_limit: executor.instruction_count_limit,
             memory_limit_kibibytes: executor.memory_limit_kibibytes,
-            executable_path: executor.executable_path,
+            stack_limit_kibibytes: executor.stack_limit_kibibytes,
+            rlimits: executor.rlimits,
+            cpu_affinity: executor.cpu_affinity,
+            supervisor_cpu_affinity: executor.supervisor_cpu_affinity,
+            nice: executor.nice,
+            scheduling_policy: executor.scheduling_policy,
+            io_priority: executor.io_priority,
+            output_limit_bytes: executor.output_limit_bytes,
+            thread_limit: executor.thread_limit,
+            trace_all_tasks: executor.thread_limit.is_some()
+                || executor.features.contains(Feature::SECCOMP)
+                || executor.features.contains(Feature::SYSCALL_AUDIT),
+            executable_path: find_executable(executor.executable_path),
             args: executor.args,
+            env,
+            argv,
+            envp,
             working_dir: executor.working_dir,
-            stdin_fd: executor.stdin_fd,
-            stdout_fd: executor.stdout_fd,
-            stderr_fd: executor.stderr_fd,
-        }
+            close_inherited_fds: executor.close_inherited_fds,
+            fd_mappings,
+            syscall_policy: executor.syscall_policy,
+        })
     }
 }
 
 impl ExecutionData {
-    pub(crate) fn new() -> ExecutionData {
+    pub(crate) fn new(
+        seccomp_filter: Option<SeccompFilter>,
+        home_dir: Option<TempDir>,
+        stdio_fds: [Option<OwnedFd>; 3],
+    ) -> ExecutionData {
         ExecutionData {
             pid_fd: None,
             raw_pid_fd: -1,
             pid: None,
+            seccomp_filter,
+            home_dir,
+            raw_seccomp_listener_fd: -1,
+            stdio_fds,
             execution_result: ExecutionResult::new(),
+            captured_stdout: None,
+            captured_stderr: None,
             child_error: None,
             child_stack: unsafe { std::mem::zeroed() },
             child_ready_barrier: Barrier::new(2),
@@ -78,3 +170,52 @@ impl ExecutionData {
         }
     }
 }
+
+/// Builds the environment of the child from the environment of the parent and the changes made to it.
+fn child_env(env_clear: bool, env_vars: BTreeMap<OsString, Option<OsString>>) -> io::Result<Vec<CString>> {
+    let mut env: BTreeMap<OsString, OsString> = if env_clear { BTreeMap::new() } else { env::vars_os().collect() };
+    for (key, val) in env_vars {
+        match val {
+            Some(val) => env.insert(key, val),
+            None => env.remove(&key),
+        };
+    }
+
+    env.into_iter()
+        .map(|(key, val)| {
+            let mut variable = key.into_vec();
+            variable.push(b'=');
+            variable.extend(val.into_vec());
+            CString::new(variable).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, "environment variables can't contain nul bytes")
+            })
+        })
+        .collect()
+}
+
+/// Searches for the program in the `PATH` of the parent the same way `execvp` does, so the child can execute it
+/// with `execve` directly. If no executable file is found, the path `execve` fails with the same error for is returned.
+fn find_executable(program: CString) -> CString {
+    if program.as_bytes().is_empty() || program.as_bytes().contains(&b'/') {
+        return program;
+    }
+
+    let path = env::var_os("PATH").unwrap_or_else(|| "/bin:/usr/bin".into());
+    let candidates: Vec<PathBuf> = env::split_paths(&path)
+        .map(|dir| dir.join(OsStr::from_bytes(program.as_bytes())))
+        .collect();
+    let executable = candidates.iter()
+        .find(|candidate| candidate.is_file() && access(candidate.as_path(), AccessFlags::X_OK).is_ok())
+        .or_else(|| candidates.iter().find(|candidate| candidate.exists()))
+        .or(candidates.first());
+
+    match executable {
+        Some(executable) => CString::new(executable.as_os_str().as_bytes()).expect("PATH can't contain nul bytes"),
+        None => program,
+    }
+}
+
+/// Returns the null-terminated array of pointers to `strings`, which stays valid as long as they aren't dropped.
+fn null_terminated(strings: &[CString]) -> Vec<*const c_char> {
+    strings.iter().map(|string| string.as_ptr()).chain(iter::once(null())).collect()
+}
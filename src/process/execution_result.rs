@@ -1,5 +1,11 @@
+use std::fmt::{Display, Formatter};
 use std::time::Duration;
 
+use libc::{pid_t, SIGXCPU, SIGXFSZ};
+
+use crate::process::data::ExecutionSettings;
+use crate::seccomp::syscalls::syscall_name;
+use crate::seccomp::SyscallAbi;
 use crate::util::CYCLES_PER_SECOND;
 
 /// Describes the result of a perfjail child process execution after it has terminated.
@@ -44,6 +50,132 @@ pub struct ExecutionResult {
     /// This value is returned only if the [`MEMORY_MEASUREMENT`](crate::process::Feature::MEMORY_MEASUREMENT)
     /// feature flag is enabled.
     pub memory_usage_kibibytes: Option<u64>,
+    /// The largest size of the stack (as measured by the `/proc/<pid>/status` VmStk value) of the child
+    /// seen while it was running. The kernel doesn't keep track of the peak of this value, so it's only
+    /// sampled whenever perfjail checks on the child, and short spikes between the samples can be missed.
+    ///
+    /// If the child was killed by a `SIGSEGV` with this value close to the limit set with
+    /// [`Perfjail::stack_limit_kibibytes`](crate::process::Perfjail::stack_limit_kibibytes),
+    /// it has most likely overflowed its stack.
+    ///
+    /// This value is returned only if the [`MEMORY_MEASUREMENT`](crate::process::Feature::MEMORY_MEASUREMENT)
+    /// feature flag is enabled.
+    pub stack_usage_kibibytes: Option<u64>,
+    /// The largest size of the data segment and private anonymous mappings (as measured by the
+    /// `/proc/<pid>/status` VmData value) of the child seen while it was running, which is where the heap is.
+    /// Like [`stack_usage_kibibytes`](ExecutionResult::stack_usage_kibibytes), it's a sampled maximum
+    /// which can miss short spikes.
+    ///
+    /// This value is returned only if the [`MEMORY_MEASUREMENT`](crate::process::Feature::MEMORY_MEASUREMENT)
+    /// feature flag is enabled.
+    pub data_usage_kibibytes: Option<u64>,
+    /// The number of times the child program made each system call, sorted by ABI and system call number.
+    ///
+    /// This value is returned only if the [`SYSCALL_AUDIT`](crate::process::Feature::SYSCALL_AUDIT)
+    /// feature flag is enabled.
+    pub syscall_counts: Option<Vec<SyscallCount>>,
+    /// The forbidden system call the child was killed for, if it was killed for making one.
+    ///
+    /// If this value is returned, the exit status is an [`RV`](ExitStatus::RV) with a comment
+    /// describing the system call.
+    pub syscall_violation: Option<SyscallViolation>,
+    /// The length and digest of the standard output of the child program.
+    ///
+    /// This value is returned only if stdout was read with [`Perfjail::stdout_pump`](crate::process::Perfjail::stdout_pump).
+    pub stdout_digest: Option<OutputDigest>,
+    /// The length and digest of the standard error of the child program.
+    ///
+    /// This value is returned only if stderr was read with [`Perfjail::stderr_pump`](crate::process::Perfjail::stderr_pump).
+    pub stderr_digest: Option<OutputDigest>,
+}
+
+/// The number of times the child program made a system call, as recorded by the
+/// [`SYSCALL_AUDIT`](crate::process::Feature::SYSCALL_AUDIT) feature.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SyscallCount {
+    /// The ABI the system call was made through.
+    pub abi: SyscallAbi,
+    /// The number of the system call in its ABI.
+    pub number: i64,
+    /// The name of the system call, or [`None`] if perfjail doesn't know the system call.
+    pub name: Option<&'static str>,
+    /// The number of times the system call was made.
+    pub count: u64,
+}
+
+/// The length and SHA-256 digest of an output stream of the child program, read by an
+/// [`OutputPump`](crate::process::OutputPump).
+///
+/// If the output limit of the pump was exceeded, only the bytes within the limit are included.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct OutputDigest {
+    /// The number of bytes read from the stream.
+    pub length: u64,
+    /// The SHA-256 digest of the bytes read from the stream.
+    pub sha256: [u8; 32],
+}
+
+impl OutputDigest {
+    /// Returns the SHA-256 digest as a lowercase hexadecimal string, like the one printed by `sha256sum`.
+    pub fn sha256_hex(&self) -> String {
+        self.sha256.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+}
+
+/// A forbidden system call made by the child program, for which it was killed by the
+/// [`SECCOMP`](crate::process::Feature::SECCOMP) feature.
+///
+/// Its [`Display`] implementation produces the comment of the [`RV`](ExitStatus::RV) exit status,
+/// for example `intercepted forbidden syscall kill(0x1, 0x9, 0x0, 0x0, 0x0, 0x0)`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SyscallViolation {
+    /// The ABI the system call was made through, or [`None`] if perfjail doesn't know the ABI.
+    pub abi: Option<SyscallAbi>,
+    /// The number of the system call in its ABI.
+    pub number: i64,
+    /// The name of the system call, or [`None`] if perfjail doesn't know the system call.
+    pub name: Option<&'static str>,
+    /// The arguments the system call was made with. As the number of arguments a system call takes
+    /// isn't known to perfjail, the unused ones contain whatever their registers held.
+    pub args: [u64; 6],
+    /// The thread id of the thread which made the system call.
+    pub tid: pid_t,
+    /// The rule of the [`SyscallPolicy`](crate::seccomp::SyscallPolicy) which matched the system call,
+    /// in the text format of policy files, or [`None`] if no rule matched and the default action was taken.
+    pub rule: Option<String>,
+}
+
+impl SyscallViolation {
+    pub(crate) fn new(abi: Option<SyscallAbi>, number: i64, args: [u64; 6], tid: pid_t, rule: Option<String>) -> SyscallViolation {
+        SyscallViolation {
+            abi,
+            number,
+            name: abi.and_then(|abi| syscall_name(abi, number)),
+            args,
+            tid,
+            rule,
+        }
+    }
+}
+
+impl Display for SyscallViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "intercepted forbidden syscall ")?;
+        match self.name {
+            Some(name) => write!(f, "{name}(")?,
+            None => write!(f, "{}(", self.number)?,
+        }
+        for (index, argument) in self.args.iter().enumerate() {
+            write!(f, "{}{argument:#x}", if index == 0 { "" } else { ", " })?;
+        }
+        write!(f, ")")?;
+
+        match self.abi {
+            Some(SyscallAbi::NATIVE) => Ok(()),
+            Some(abi) => write!(f, " made through the {abi} ABI"),
+            None => write!(f, " made through an unknown ABI"),
+        }
+    }
 }
 
 /// A list of possible rules violations and run errors that can occur during the running of the child program.
@@ -56,6 +188,8 @@ pub enum ExitStatus {
     /// Runtime error - the child process encountered a runtime error (for example, a segmentation fault).   
     RE(String),
     /// Rules violation - a sandboxing rule was violated (for example, a thread was created when the thread limit was zero).
+    ///
+    /// If a forbidden system call was made, its details are available in [`ExecutionResult::syscall_violation`].
     RV(String),
     /// Time limit exceeded - a time limit set in the [`Perfjail`](crate::process::Perfjail) builder was exceeded.
     TLE(String),
@@ -105,6 +239,12 @@ impl ExecutionResult {
             user_time: None,
             system_time: None,
             memory_usage_kibibytes: None,
+            stack_usage_kibibytes: None,
+            data_usage_kibibytes: None,
+            syscall_counts: None,
+            syscall_violation: None,
+            stdout_digest: None,
+            stderr_digest: None,
         }
     }
 
@@ -114,13 +254,20 @@ impl ExecutionResult {
         }
     }
 
-    pub(crate) fn set_exit_reason(&mut self, exit_reason: ExitReason) {
+    pub(crate) fn set_exit_reason(&mut self, exit_reason: ExitReason, settings: &ExecutionSettings) {
         self.exit_reason = exit_reason;
 
         match exit_reason {
             ExitReason::Exited { exit_status: 0 } => {}
             ExitReason::Exited { exit_status } =>
                 self.set_exit_status(ExitStatus::RE(format!("runtime error: return value {exit_status}"))),
+            // The signal is sent when the child exceeds the file size limit the output limit is enforced with,
+            // but the child can also get it for other reasons (for example, by sending it to itself)
+            ExitReason::Killed { signal: SIGXFSZ } if settings.output_limit_bytes.is_some() =>
+                self.set_exit_status(ExitStatus::OLE("output limit exceeded".into())),
+            // Similarly, the signal is sent when the child exceeds the CPU time backstop
+            ExitReason::Killed { signal: SIGXCPU } if settings.cpu_time_backstop.is_some() =>
+                self.set_exit_status(ExitStatus::TLE("time limit exceeded".into())),
             ExitReason::Killed { signal } =>
                 self.set_exit_status(ExitStatus::RE(format!("runtime error: killed by signal {signal}"))),
         }
@@ -148,4 +295,33 @@ impl ExecutionResult {
     pub(crate) fn set_memory_usage_kibibytes(&mut self, memory_usage_kibibytes: u64) {
         self.memory_usage_kibibytes = Some(memory_usage_kibibytes)
     }
+
+    pub(crate) fn set_stack_usage_kibibytes(&mut self, stack_usage_kibibytes: u64) {
+        self.stack_usage_kibibytes = Some(stack_usage_kibibytes)
+    }
+
+    pub(crate) fn set_data_usage_kibibytes(&mut self, data_usage_kibibytes: u64) {
+        self.data_usage_kibibytes = Some(data_usage_kibibytes)
+    }
+
+    /// Sets the exit status to an [`RV`](ExitStatus::RV) describing `syscall_violation`, unless another
+    /// exit status has already been set.
+    pub(crate) fn set_syscall_violation(&mut self, syscall_violation: SyscallViolation) {
+        if self.exit_status == ExitStatus::OK {
+            self.exit_status = ExitStatus::RV(syscall_violation.to_string());
+            self.syscall_violation = Some(syscall_violation);
+        }
+    }
+
+    pub(crate) fn set_syscall_counts(&mut self, syscall_counts: Vec<SyscallCount>) {
+        self.syscall_counts = Some(syscall_counts)
+    }
+
+    pub(crate) fn set_stdout_digest(&mut self, stdout_digest: OutputDigest) {
+        self.stdout_digest = Some(stdout_digest)
+    }
+
+    pub(crate) fn set_stderr_digest(&mut self, stderr_digest: OutputDigest) {
+        self.stderr_digest = Some(stderr_digest)
+    }
 }
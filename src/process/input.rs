@@ -0,0 +1,108 @@
+use crate::util::sealed_memfd;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
+/// An input loaded into memory once, which can be given as the standard input to many
+/// [`Perfjail`](crate::process::Perfjail) runs, including concurrent ones.
+///
+/// The input is stored in a sealed file in memory (a memfd), so it can't be modified anymore once it's loaded.
+/// Every call to [`reopen`](SharedInput::reopen) opens the file again through `/proc/self/fd`, so each run reads
+/// it from the start with its own file offset, without the runs competing for the page cache of the file on disk
+/// or for a shared offset.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use perfjail::process::{Perfjail, SharedInput};
+/// use std::thread;
+///
+/// let input = SharedInput::from_reader(&b"1 2 3\n"[..]).expect("failed to load input");
+///
+/// thread::scope(|scope| {
+///     for _ in 0..4 {
+///         scope.spawn(|| {
+///             Perfjail::new("cat")
+///                 .stdin(input.reopen().expect("failed to reopen input"))
+///                 .spawn()
+///                 .expect("failed to spawn child")
+///                 .run()
+///                 .expect("failed to run cat")
+///         });
+///     }
+/// });
+/// ```
+#[derive(Debug)]
+pub struct SharedInput {
+    file: File,
+}
+
+impl SharedInput {
+    /// Loads the file at `path` into memory.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```no_run
+    /// use perfjail::process::SharedInput;
+    ///
+    /// let input = SharedInput::open("tests/bud.in").expect("failed to load input");
+    /// ```
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<SharedInput> {
+        SharedInput::from_reader(File::open(path)?)
+    }
+
+    /// Loads everything read from `reader` into memory.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::SharedInput;
+    ///
+    /// let input = SharedInput::from_reader(&b"1 2 3\n"[..]).expect("failed to load input");
+    /// assert_eq!(input.len().unwrap(), 6);
+    /// ```
+    pub fn from_reader<R: Read>(reader: R) -> io::Result<SharedInput> {
+        Ok(SharedInput { file: sealed_memfd(c"perfjail-input", reader)? })
+    }
+
+    /// Returns the size of the input in bytes.
+    pub fn len(&self) -> io::Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    /// Returns `true` if the input is empty.
+    pub fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Opens the input again, returning a read-only file with its own offset at the start of the input,
+    /// which can be passed to [`Perfjail::stdin`](crate::process::Perfjail::stdin).
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::{Perfjail, SharedInput};
+    ///
+    /// let input = SharedInput::from_reader(&b"1 2 3\n"[..]).expect("failed to load input");
+    ///
+    /// Perfjail::new("cat")
+    ///     .stdin(input.reopen().expect("failed to reopen input"))
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run cat");
+    /// ```
+    pub fn reopen(&self) -> io::Result<File> {
+        File::open(format!("/proc/self/fd/{}", self.file.as_raw_fd()))
+    }
+}
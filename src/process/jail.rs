@@ -1,9 +1,18 @@
 use enumset::{EnumSet, EnumSetType};
-use libc::{pthread_attr_destroy, pthread_attr_init, pthread_attr_setdetachstate, pthread_attr_t, pthread_create, pthread_t, PTHREAD_CREATE_DETACHED};
-use std::ffi::{c_int, CString, OsStr};
-use std::os::fd::{BorrowedFd, FromRawFd, OwnedFd};
+use linear_map::LinearMap;
+use nix::sched::CpuSet;
+use nix::sys::resource::Resource;
+use libc::{
+    pthread_attr_destroy, pthread_attr_init, pthread_attr_setdetachstate, pthread_attr_t, pthread_create, pthread_t,
+    PTHREAD_CREATE_DETACHED, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO,
+};
+use std::collections::BTreeMap;
+use std::ffi::{c_int, CString, OsStr, OsString};
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use std::io::{PipeReader, PipeWriter};
 use std::{fs, io, mem};
 
 use crate::listener::perf::PerfListener;
@@ -11,9 +20,13 @@ use crate::listener::Listener;
 use crate::listener::memory::MemoryListener;
 use crate::listener::time::TimeListener;
 use crate::listener::ptrace::PtraceListener;
+use crate::listener::output::OutputListener;
 use crate::process::child::{clone_and_execute, JailedChild};
 use crate::process::data::{ExecutionContext, ExecutionData, ExecutionSettings};
-use crate::util::{cvt_no_errno, CYCLES_PER_SECOND};
+use crate::process::{IoPriority, Output, OutputPump, SchedulingPolicy, Stdio};
+use crate::seccomp::filter::SeccompFilter;
+use crate::seccomp::{SyscallHandler, SyscallPolicy};
+use crate::util::{cpu_set, cvt_no_errno, TempDir, CYCLES_PER_SECOND};
 
 /// A builder based on [`std::process::Command`] used to configure and spawn perfjail processes.
 ///
@@ -34,19 +47,40 @@ use crate::util::{cvt_no_errno, CYCLES_PER_SECOND};
 ///
 /// Unlike [`std::process::Command`], `Perfjail` cannot be used to spawn multiple
 /// processes, as [`Perfjail::spawn`] consumes itself after it's called.
-pub struct Perfjail<'a> {
+pub struct Perfjail {
     pub(crate) real_time_limit: Option<Duration>,
     pub(crate) user_time_limit: Option<Duration>,
     pub(crate) system_time_limit: Option<Duration>,
     pub(crate) user_system_time_limit: Option<Duration>,
+    pub(crate) cpu_time_backstop_margin: Option<Duration>,
     pub(crate) instruction_count_limit: Option<i64>,
     pub(crate) memory_limit_kibibytes: Option<u64>,
+    pub(crate) stack_limit_kibibytes: Option<u64>,
+    pub(crate) rlimits: LinearMap<Resource, (u64, u64)>,
+    pub(crate) cpu_affinity: Option<CpuSet>,
+    pub(crate) supervisor_cpu_affinity: Option<CpuSet>,
+    pub(crate) nice: Option<i32>,
+    pub(crate) scheduling_policy: Option<SchedulingPolicy>,
+    pub(crate) io_priority: Option<IoPriority>,
+    pub(crate) output_limit_bytes: Option<u64>,
+    pub(crate) thread_limit: Option<u32>,
     pub(crate) executable_path: CString,
     pub(crate) args: Vec<CString>,
     pub(crate) working_dir: Option<PathBuf>,
-    pub(crate) stdin_fd: Option<BorrowedFd<'a>>,
-    pub(crate) stdout_fd: Option<BorrowedFd<'a>>,
-    pub(crate) stderr_fd: Option<BorrowedFd<'a>>,
+    pub(crate) env_clear: bool,
+    /// The environment variables set (or removed, if the value is [`None`]) for the child.
+    pub(crate) env_vars: BTreeMap<OsString, Option<OsString>>,
+    pub(crate) fresh_home: bool,
+    pub(crate) stdin: Stdio,
+    pub(crate) stdout: Stdio,
+    pub(crate) stderr: Stdio,
+    pub(crate) stdout_pump: Option<OutputPump>,
+    pub(crate) stderr_pump: Option<OutputPump>,
+    pub(crate) close_inherited_fds: bool,
+    /// The file descriptors made available in the child, by their number in the child.
+    pub(crate) fd_mappings: BTreeMap<RawFd, OwnedFd>,
+    pub(crate) syscall_policy: Option<SyscallPolicy>,
+    pub(crate) syscall_handler: Option<Box<dyn SyscallHandler + Send>>,
     pub(crate) features: EnumSet<Feature>,
 }
 
@@ -67,18 +101,44 @@ pub enum Feature {
     TIME_MEASUREMENT,
     /// Makes the [`ExecutionResult`](crate::process::ExecutionResult) returned by [`JailedChild::run`] include the
     /// [`memory_usage_kibibytes`](crate::process::execution_result::ExecutionResult::memory_usage_kibibytes),
-    /// field.
+    /// [`stack_usage_kibibytes`](crate::process::execution_result::ExecutionResult::stack_usage_kibibytes) and
+    /// [`data_usage_kibibytes`](crate::process::execution_result::ExecutionResult::data_usage_kibibytes) fields.
+    ///
+    /// Unless a limit is set with [`stack_limit_kibibytes`](Perfjail::stack_limit_kibibytes), this feature
+    /// raises the stack size limit of the child to the highest possible value.
     MEMORY_MEASUREMENT,
+    /// Restricts the system calls the child program can make according to the
+    /// [`SyscallPolicy`] set with [`syscall_policy`](Perfjail::syscall_policy), or
+    /// [`SyscallPolicy::sio2jail_default`] if no policy was set.
+    /// If the child makes a system call the policy forbids, it is killed and
+    /// [`ExitStatus::RV`](crate::process::ExitStatus::RV) is returned as the exit status, with the details of
+    /// the system call in [`ExecutionResult::syscall_violation`](crate::process::ExecutionResult::syscall_violation).
+    ///
+    /// The programs executed by the processes the child program creates are checked against the policy too,
+    /// so every thread and process of the child is traced, which requires the child to be run in a new process group.
+    /// Neither the child nor the processes it creates can leave the group, as `setpgid` and `setsid` always
+    /// fail with `EPERM`, regardless of the policy.
+    SECCOMP,
+    /// Makes the [`ExecutionResult`](crate::process::ExecutionResult) returned by [`JailedChild::run`] include the
+    /// [`syscall_counts`](crate::process::execution_result::ExecutionResult::syscall_counts) field,
+    /// counting every system call the child program makes.
+    ///
+    /// This feature doesn't forbid any system calls by itself, so it can be used to find out which
+    /// system calls a program needs before restricting it with the [`SECCOMP`](Feature::SECCOMP) feature.
+    /// The system calls made by all the threads and processes created by the child program are counted too,
+    /// which requires the child to be run in a new process group, which it can't leave (`setpgid` and `setsid`
+    /// fail with `EPERM`).
+    SYSCALL_AUDIT,
 }
 
 #[allow(dead_code)]
-impl<'a> Perfjail<'a> {
+impl Perfjail {
     /// Constructs a new `Perfjail` for launching the program at path `program`, with the following default configuration:
     ///
     /// - No arguments to the program
-    /// - Inherit the current process’s environment
+    /// - Inherit the current process’s environment, unless it's changed with [`env`](Perfjail::env) or [`env_clear`](Perfjail::env_clear)
     /// - Inherit the current process’s working directory
-    /// - Inherit stdin/stdout/stderr
+    /// - Inherit stdin/stdout/stderr, closing all the other file descriptors
     /// - Don't enable any features and don't set and time limits
     ///
     /// Builder methods are provided to change these defaults and otherwise configure the process.
@@ -117,22 +177,41 @@ impl<'a> Perfjail<'a> {
     ///     .spawn()
     ///     .expect("ls command failed to start");
     /// ```
-    pub fn new<S: AsRef<OsStr>>(program: S) -> Perfjail<'a> {
+    pub fn new<S: AsRef<OsStr>>(program: S) -> Perfjail {
         Perfjail {
             real_time_limit: None,
             user_time_limit: None,
             system_time_limit: None,
             user_system_time_limit: None,
+            cpu_time_backstop_margin: None,
             instruction_count_limit: None,
             memory_limit_kibibytes: None,
+            stack_limit_kibibytes: None,
+            rlimits: LinearMap::new(),
+            cpu_affinity: None,
+            supervisor_cpu_affinity: None,
+            nice: None,
+            scheduling_policy: None,
+            io_priority: None,
+            output_limit_bytes: None,
+            thread_limit: None,
             executable_path: CString::new(program.as_ref().as_encoded_bytes())
                 .expect("Failed to convert program path to CString"),
             args: vec![CString::new(program.as_ref().as_encoded_bytes())
                 .expect("Failed to convert program path to CString")],
             working_dir: None,
-            stdin_fd: None,
-            stdout_fd: None,
-            stderr_fd: None,
+            env_clear: false,
+            env_vars: BTreeMap::new(),
+            fresh_home: false,
+            stdin: Stdio::Inherit,
+            stdout: Stdio::Inherit,
+            stderr: Stdio::Inherit,
+            stdout_pump: None,
+            stderr_pump: None,
+            close_inherited_fds: true,
+            fd_mappings: BTreeMap::new(),
+            syscall_policy: None,
+            syscall_handler: None,
             features: EnumSet::new(),
         }
     }
@@ -177,7 +256,7 @@ impl<'a> Perfjail<'a> {
     ///     .run()
     ///     .expect("failed to run ls");
     /// ```
-    pub fn arg<S: AsRef<OsStr>>(mut self, arg: S) -> Perfjail<'a> {
+    pub fn arg<S: AsRef<OsStr>>(mut self, arg: S) -> Perfjail {
         self.args.push(
             CString::new(arg.as_ref().as_encoded_bytes())
                 .expect("Failed to convert program arg to CString"),
@@ -208,7 +287,7 @@ impl<'a> Perfjail<'a> {
     ///     .run()
     ///     .expect("failed to run ls");
     /// ```
-    pub fn args<I, S>(mut self, args: I) -> Perfjail<'a>
+    pub fn args<I, S>(mut self, args: I) -> Perfjail
     where
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
@@ -243,14 +322,24 @@ impl<'a> Perfjail<'a> {
     ///     .run()
     ///     .expect("failed to run ls");
     /// ```
-    pub fn current_dir<P: AsRef<Path>>(mut self, dir: P) -> Perfjail<'a> {
+    pub fn current_dir<P: AsRef<Path>>(mut self, dir: P) -> Perfjail {
         self.working_dir = Some(PathBuf::from(dir.as_ref().as_os_str()));
         self
     }
 
-    /// Sets the file descriptor for the child process’s standard input (stdin).
+    /// Inserts or updates an explicit environment variable mapping.
+    ///
+    /// This method allows you to add an environment variable mapping to the spawned process or overwrite a
+    /// previously set value. You can use [`envs`](Perfjail::envs) to set multiple environment variables simultaneously.
+    ///
+    /// Child processes will inherit environment variables from their parent process by default.
+    /// Environment variables explicitly set using [`env`](Perfjail::env) take precedence over inherited variables.
+    /// You can disable environment variable inheritance entirely using [`env_clear`](Perfjail::env_clear) or
+    /// for a single key using [`env_remove`](Perfjail::env_remove).
     ///
-    /// If this function is not called, child stdin is inherited from the parent process.
+    /// Note that environment variable names are case-insensitive (but case-preserving) on Windows
+    /// and case-sensitive on all other platforms. Unlike [`std::process::Command`], the program is always
+    /// searched for using the `PATH` of the parent process.
     ///
     /// # Examples
     ///
@@ -258,26 +347,203 @@ impl<'a> Perfjail<'a> {
     ///
     /// ```
     /// use perfjail::process::Perfjail;
-    /// use std::fs::File;
-    /// use std::os::fd::AsFd;
     ///
-    /// let file = File::open("/dev/null").unwrap();
+    /// Perfjail::new("ls")
+    ///     .env("PATH", "/bin")
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run ls");
+    /// ```
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(mut self, key: K, val: V) -> Perfjail {
+        self.env_vars.insert(key.as_ref().to_owned(), Some(val.as_ref().to_owned()));
+        self
+    }
+
+    /// Inserts or updates multiple explicit environment variable mappings.
+    ///
+    /// This method allows you to add multiple environment variable mappings to the spawned process or overwrite
+    /// previously set values. You can use [`env`](Perfjail::env) to set a single environment variable.
+    ///
+    /// Child processes will inherit environment variables from their parent process by default.
+    /// Environment variables explicitly set using [`envs`](Perfjail::envs) take precedence over inherited variables.
+    /// You can disable environment variable inheritance entirely using [`env_clear`](Perfjail::env_clear) or
+    /// for a single key using [`env_remove`](Perfjail::env_remove).
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::Perfjail;
+    ///
+    /// Perfjail::new("printenv")
+    ///     .env_clear()
+    ///     .envs([("LANG", "C"), ("TZ", "UTC")])
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run printenv");
+    /// ```
+    pub fn envs<I, K, V>(mut self, vars: I) -> Perfjail
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        for (key, val) in vars {
+            self = self.env(key, val);
+        }
+        self
+    }
+
+    /// Removes an explicitly set environment variable and prevents inheriting it from a parent process.
+    ///
+    /// This method will remove the explicit value of an environment variable set via [`env`](Perfjail::env) or
+    /// [`envs`](Perfjail::envs). In addition, it will prevent the spawned child process from inheriting that
+    /// environment variable from its parent process.
+    ///
+    /// To clear all explicitly set environment variables and disable all environment variable inheritance,
+    /// you can use [`env_clear`](Perfjail::env_clear).
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::Perfjail;
     ///
     /// Perfjail::new("ls")
-    ///     .stdin(file.as_fd())
+    ///     .env_remove("PATH")
     ///     .spawn()
     ///     .expect("failed to spawn child")
     ///     .run()
     ///     .expect("failed to run ls");
     /// ```
-    pub fn stdin<T: Into<BorrowedFd<'a>>>(mut self, fd: T) -> Perfjail<'a> {
-        self.stdin_fd = Some(fd.into());
+    pub fn env_remove<K: AsRef<OsStr>>(mut self, key: K) -> Perfjail {
+        self.env_vars.insert(key.as_ref().to_owned(), None);
+        self
+    }
+
+    /// Clears all explicitly set environment variables and prevents inheriting any parent process environment variables.
+    ///
+    /// This method will remove all explicitly added environment variables set via [`env`](Perfjail::env) or
+    /// [`envs`](Perfjail::envs). In addition, it will prevent the spawned child process from inheriting any
+    /// environment variable from its parent process.
+    ///
+    /// After calling this method, you can use [`env`](Perfjail::env) or [`envs`](Perfjail::envs) to set
+    /// environment variables for the child process.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::Perfjail;
+    ///
+    /// Perfjail::new("ls")
+    ///     .env_clear()
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run ls");
+    /// ```
+    pub fn env_clear(mut self) -> Perfjail {
+        self.env_clear = true;
+        self.env_vars.clear();
+        self
+    }
+
+    /// Sets the `HOME` and `TMPDIR` environment variables of the child to a new empty directory,
+    /// so the child can't read or modify the files in the ones of the parent process.
+    ///
+    /// The directory is created in the temporary directory of the parent when the child is spawned, and it's
+    /// removed along with its contents when the [`JailedChild`] is dropped. These variables take precedence
+    /// over the ones set with [`env`](Perfjail::env) and [`envs`](Perfjail::envs).
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::ExitStatus::OK;
+    /// use perfjail::process::Perfjail;
+    ///
+    /// let result = Perfjail::new("sh")
+    ///     .args(["-c", "test -z \"$(ls -A $HOME)\" && test $HOME = $TMPDIR"])
+    ///     .fresh_home()
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run sh");
+    ///
+    /// assert_eq!(result.exit_status, OK);
+    /// ```
+    pub fn fresh_home(mut self) -> Perfjail {
+        self.fresh_home = true;
+        self
+    }
+
+    /// Configuration for the child process’s standard input (stdin) handle.
+    ///
+    /// Defaults to [`Stdio::Inherit`]. Anything which can be converted into a [`Stdio`],
+    /// such as a [`File`](std::fs::File), can be passed directly.
+    /// Calling this function replaces the data set with [`stdin_bytes`](Perfjail::stdin_bytes), and vice versa.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::{Perfjail, Stdio};
+    ///
+    /// Perfjail::new("ls")
+    ///     .stdin(Stdio::Null)
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run ls");
+    /// ```
+    pub fn stdin<T: Into<Stdio>>(mut self, cfg: T) -> Perfjail {
+        self.stdin = cfg.into();
+        self
+    }
+
+    /// Sets the data the child process reads from its standard input (stdin).
+    ///
+    /// The data is written to a sealed file in memory (a memfd) when the child is spawned, so unlike with a pipe,
+    /// the child can't block on its input while the parent is waiting for it, and it sees its input as a regular,
+    /// seekable file, like one opened from disk.
+    ///
+    /// This is the same as calling [`stdin`](Perfjail::stdin) with [`Stdio::Bytes`], so whichever of the two
+    /// is called last determines the standard input of the child.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::{OutputPump, Perfjail};
+    ///
+    /// let output = Perfjail::new("wc")
+    ///     .arg("-l")
+    ///     .stdin_bytes("1\n2\n3\n")
+    ///     .stdout_pump(OutputPump::new().capture(1024))
+    ///     .output()
+    ///     .expect("failed to run wc");
+    ///
+    /// assert_eq!(output.stdout, b"3\n");
+    /// ```
+    pub fn stdin_bytes<B: Into<Vec<u8>>>(mut self, bytes: B) -> Perfjail {
+        self.stdin = Stdio::Bytes(bytes.into());
         self
     }
 
-    /// Sets the file descriptor for the child process’s standard output (stdout).
+    /// Configuration for the child process’s standard output (stdout) handle.
     ///
-    /// If this function is not called, child stdout is inherited from the parent process.
+    /// Defaults to [`Stdio::Inherit`]. Anything which can be converted into a [`Stdio`],
+    /// such as a [`File`](std::fs::File), can be passed directly.
+    /// Calling this function replaces the pump set with [`stdout_pump`](Perfjail::stdout_pump).
     ///
     /// # Examples
     ///
@@ -286,46 +552,180 @@ impl<'a> Perfjail<'a> {
     /// ```
     /// use perfjail::process::Perfjail;
     /// use std::fs::File;
-    /// use std::os::fd::AsFd;
     ///
-    /// let file = File::open("/dev/null").unwrap();
+    /// let file = File::create("/tmp/perfjail_stdout_example.out").unwrap();
     ///
     /// Perfjail::new("ls")
-    ///     .stdout(file.as_fd())
+    ///     .stdout(file)
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run ls");
+    /// ```
+    pub fn stdout<T: Into<Stdio>>(mut self, cfg: T) -> Perfjail {
+        self.stdout = cfg.into();
+        self.stdout_pump = None;
+        self
+    }
+
+    /// Configuration for the child process’s standard error (stderr) handle.
+    ///
+    /// Defaults to [`Stdio::Inherit`]. Anything which can be converted into a [`Stdio`],
+    /// such as a [`File`](std::fs::File), can be passed directly.
+    /// Calling this function replaces the pump set with [`stderr_pump`](Perfjail::stderr_pump).
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::{Perfjail, Stdio};
+    /// use std::io::Read;
+    ///
+    /// let mut child = Perfjail::new("ls")
+    ///     .arg("/nonexistent")
+    ///     .stderr(Stdio::Piped)
+    ///     .spawn()
+    ///     .expect("failed to spawn child");
+    ///
+    /// let mut stderr = child.stderr.take().unwrap();
+    /// let reader = std::thread::spawn(move || {
+    ///     let mut output = String::new();
+    ///     stderr.read_to_string(&mut output).map(|_| output)
+    /// });
+    /// child.run().expect("failed to run ls");
+    ///
+    /// assert!(reader.join().unwrap().unwrap().contains("/nonexistent"));
+    /// ```
+    pub fn stderr<T: Into<Stdio>>(mut self, cfg: T) -> Perfjail {
+        self.stderr = cfg.into();
+        self.stderr_pump = None;
+        self
+    }
+
+    /// Redirects the child process’s standard output (stdout) to a pipe read by the supervisor as described
+    /// in [`OutputPump`], which hashes it, can limit its length and can copy it to a file.
+    /// The length and digest of the output are returned in
+    /// [`ExecutionResult::stdout_digest`](crate::process::ExecutionResult::stdout_digest).
+    ///
+    /// Calling this function replaces the configuration set with [`stdout`](Perfjail::stdout).
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::{OutputPump, Perfjail};
+    ///
+    /// let result = Perfjail::new("ls")
+    ///     .stdout_pump(OutputPump::new().limit_bytes(1024))
     ///     .spawn()
     ///     .expect("failed to spawn child")
     ///     .run()
     ///     .expect("failed to run ls");
+    ///
+    /// println!("ls printed {} bytes", result.stdout_digest.as_ref().unwrap().length);
     /// ```
-    pub fn stdout<T: Into<BorrowedFd<'a>>>(mut self, fd: T) -> Perfjail<'a> {
-        self.stdout_fd = Some(fd.into());
+    pub fn stdout_pump(mut self, pump: OutputPump) -> Perfjail {
+        self.stdout_pump = Some(pump);
+        self.stdout = Stdio::Inherit;
         self
     }
 
-    /// Sets the file descriptor for the child process’s standard error (stderr).
+    /// Redirects the child process’s standard error (stderr) to a pipe read by the supervisor as described
+    /// in [`OutputPump`], which hashes it, can limit its length and can copy it to a file.
+    /// The length and digest of the output are returned in
+    /// [`ExecutionResult::stderr_digest`](crate::process::ExecutionResult::stderr_digest).
     ///
-    /// If this function is not called, child stderr is inherited from the parent process.
+    /// Calling this function replaces the configuration set with [`stderr`](Perfjail::stderr).
     ///
     /// # Examples
     ///
     /// Basic usage:
     ///
     /// ```
+    /// use perfjail::process::{OutputPump, Perfjail};
+    ///
+    /// let result = Perfjail::new("ls")
+    ///     .arg("/nonexistent")
+    ///     .stderr_pump(OutputPump::new())
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run ls");
+    ///
+    /// assert!(result.stderr_digest.as_ref().unwrap().length > 0);
+    /// ```
+    pub fn stderr_pump(mut self, pump: OutputPump) -> Perfjail {
+        self.stderr_pump = Some(pump);
+        self.stderr = Stdio::Inherit;
+        self
+    }
+
+    /// Makes the file descriptor `fd` available as the file descriptor number `child_fd` in the child process,
+    /// like the `3<file` redirection of a shell does. Anything which can be converted into an [`OwnedFd`],
+    /// such as a [`File`](std::fs::File), can be passed directly.
+    /// If `child_fd` is mapped multiple times, the last mapping is used.
+    ///
+    /// This can be used for passing additional files or pipes to the child program, for example
+    /// the channels used for communicating with an interactor. Mapped file descriptors are never closed
+    /// by [`close_inherited_fds`](Perfjail::close_inherited_fds).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `child_fd` is one of the standard streams (0, 1 or 2), which are set with
+    /// [`stdin`](Perfjail::stdin), [`stdout`](Perfjail::stdout) and [`stderr`](Perfjail::stderr) instead.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::ExitStatus::OK;
     /// use perfjail::process::Perfjail;
     /// use std::fs::File;
-    /// use std::os::fd::AsFd;
     ///
     /// let file = File::open("/dev/null").unwrap();
     ///
+    /// let result = Perfjail::new("sh")
+    ///     .args(["-c", "cat <&3"])
+    ///     .fd_map(3, file)
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run sh");
+    ///
+    /// assert_eq!(result.exit_status, OK);
+    /// ```
+    pub fn fd_map<T: Into<OwnedFd>>(mut self, child_fd: RawFd, fd: T) -> Perfjail {
+        assert!(child_fd > 2, "the standard streams can't be mapped with fd_map");
+        self.fd_mappings.insert(child_fd, fd.into());
+        self
+    }
+
+    /// Sets whether the file descriptors of the parent process besides stdin, stdout, stderr and the ones
+    /// mapped with [`fd_map`](Perfjail::fd_map) are closed
+    /// in the child before the program is executed. This is enabled by default, so the child can't use the
+    /// files, pipes or sockets the parent has opened without `O_CLOEXEC` (including the ones of other children
+    /// running at the same time).
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::Perfjail;
+    ///
     /// Perfjail::new("ls")
-    ///     .stderr(file.as_fd())
+    ///     .arg("/proc/self/fd")
+    ///     .close_inherited_fds(false)
     ///     .spawn()
     ///     .expect("failed to spawn child")
     ///     .run()
     ///     .expect("failed to run ls");
     /// ```
-    pub fn stderr<T: Into<BorrowedFd<'a>>>(mut self, fd: T) -> Perfjail<'a> {
-        self.stderr_fd = Some(fd.into());
+    pub fn close_inherited_fds(mut self, close: bool) -> Perfjail {
+        self.close_inherited_fds = close;
         self
     }
 
@@ -349,7 +749,7 @@ impl<'a> Perfjail<'a> {
     ///     .run()
     ///     .expect("failed to run ls");
     /// ```
-    pub fn features<T: Into<EnumSet<Feature>>>(mut self, features: T) -> Perfjail<'a> {
+    pub fn features<T: Into<EnumSet<Feature>>>(mut self, features: T) -> Perfjail {
         self.features.insert_all(features.into());
         self
     }
@@ -379,7 +779,7 @@ impl<'a> Perfjail<'a> {
     ///     .run()
     ///     .expect("failed to run sleep");
     /// ```
-    pub fn real_time_limit(mut self, limit: Duration) -> Perfjail<'a> {
+    pub fn real_time_limit(mut self, limit: Duration) -> Perfjail {
         self.real_time_limit = Some(limit);
         self
     }
@@ -409,7 +809,7 @@ impl<'a> Perfjail<'a> {
     ///     .run()
     ///     .expect("failed to run sleep");
     /// ```
-    pub fn user_time_limit(mut self, limit: Duration) -> Perfjail<'a> {
+    pub fn user_time_limit(mut self, limit: Duration) -> Perfjail {
         self.user_time_limit = Some(limit);
         self
     }
@@ -439,7 +839,7 @@ impl<'a> Perfjail<'a> {
     ///     .run()
     ///     .expect("failed to run sleep");
     /// ```
-    pub fn system_time_limit(mut self, limit: Duration) -> Perfjail<'a> {
+    pub fn system_time_limit(mut self, limit: Duration) -> Perfjail {
         self.system_time_limit = Some(limit);
         self
     }
@@ -469,11 +869,49 @@ impl<'a> Perfjail<'a> {
     ///     .run()
     ///     .expect("failed to run sleep");
     /// ```
-    pub fn user_system_time_limit(mut self, limit: Duration) -> Perfjail<'a> {
+    pub fn user_system_time_limit(mut self, limit: Duration) -> Perfjail {
         self.user_system_time_limit = Some(limit);
         self
     }
 
+    /// Makes the kernel enforce the [`user_system_time_limit`](Perfjail::user_system_time_limit) (or
+    /// the [`user_time_limit`](Perfjail::user_time_limit), if it isn't set) with an additional `margin`,
+    /// through `RLIMIT_CPU`. This kills the child even if the supervisor doesn't get to check its time usage,
+    /// for example because the machine is overloaded.
+    ///
+    /// Once the child has used up the limit and the margin (rounded up to whole seconds), the kernel sends it
+    /// a `SIGXCPU`, followed by a `SIGKILL` a second later if it's still running. In both cases
+    /// [`ExitStatus::TLE`](crate::process::ExitStatus::TLE) is returned as the exit status.
+    /// As `RLIMIT_CPU` limits the total user and system time, a backstop derived from the user time
+    /// limit can also kill a child which spent the margin in the kernel.
+    ///
+    /// If neither of the limits is set, this method has no effect. Setting a backstop also automatically
+    /// enables the [`TIME_MEASUREMENT`](Feature::TIME_MEASUREMENT) feature flag, working the same way
+    /// as if it was added using the [`features`](Perfjail::features) method.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use perfjail::process::Perfjail;
+    ///
+    /// let result = Perfjail::new("sleep")
+    ///     .arg("1")
+    ///     .user_system_time_limit(Duration::from_secs(1))
+    ///     .cpu_time_backstop(Duration::from_millis(500))
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run sleep");
+    /// ```
+    pub fn cpu_time_backstop(mut self, margin: Duration) -> Perfjail {
+        self.cpu_time_backstop_margin = Some(margin);
+        self = self.features(Feature::TIME_MEASUREMENT);
+        self
+    }
+
     /// Sets a limit on how much measured time (as described in [`ExecutionResult::measured_time`](crate::process::ExecutionResult::measured_time)) can pass after the child program is executed
     /// before it is killed and [`ExitStatus::TLE`](crate::process::ExitStatus::TLE) is
     /// returned as the exit status.
@@ -498,7 +936,7 @@ impl<'a> Perfjail<'a> {
     ///     .run()
     ///     .expect("failed to run sleep");
     /// ```
-    pub fn measured_time_limit(mut self, limit: Duration) -> Perfjail<'a> {
+    pub fn measured_time_limit(mut self, limit: Duration) -> Perfjail {
         self.instruction_count_limit =
             Some((limit.as_millis() * ((CYCLES_PER_SECOND / 1_000) as u128)) as i64);
         self = self.features(Feature::PERF);
@@ -530,11 +968,407 @@ impl<'a> Perfjail<'a> {
     ///     .run()
     ///     .expect("failed to run sleep");
     /// ```
-    pub fn memory_limit_kibibytes(mut self, limit: u64) -> Perfjail<'a> {
+    pub fn memory_limit_kibibytes(mut self, limit: u64) -> Perfjail {
         self.memory_limit_kibibytes = Some(limit);
         self
     }
 
+    /// Sets a limit on the size of the stack of the child program. If the stack of the child grows past
+    /// the limit, it is killed by a `SIGSEGV` and [`ExitStatus::RE`](crate::process::ExitStatus::RE) is
+    /// returned as the exit status.
+    ///
+    /// The limit is enforced by the kernel through `RLIMIT_STACK`, and it takes priority over the
+    /// raised limit set by the [`MEMORY_MEASUREMENT`](Feature::MEMORY_MEASUREMENT) feature.
+    /// The stack size of the child is reported in
+    /// [`ExecutionResult::stack_usage_kibibytes`](crate::process::ExecutionResult::stack_usage_kibibytes),
+    /// which helps telling stack overflows apart from other segmentation faults.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::ExitStatus::OK;
+    /// use perfjail::process::Perfjail;
+    ///
+    /// let result = Perfjail::new("sh")
+    ///     .args(["-c", "test $(ulimit -s) = 1024"])
+    ///     .stack_limit_kibibytes(1024) // 1 MiB
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run sh");
+    ///
+    /// assert_eq!(result.exit_status, OK);
+    /// ```
+    pub fn stack_limit_kibibytes(mut self, limit: u64) -> Perfjail {
+        self.stack_limit_kibibytes = Some(limit);
+        self
+    }
+
+    /// Sets the soft and hard limits of a resource for the child program, which are applied with
+    /// `setrlimit` right before the program is executed. [`RLIM_INFINITY`](crate::process::RLIM_INFINITY)
+    /// can be used to make a resource unlimited. If a resource is limited multiple times, the last limits are used.
+    ///
+    /// The limits set with this method take priority over the ones set by perfjail itself, like the raised
+    /// address space and stack limits of the [`MEMORY_MEASUREMENT`](Feature::MEMORY_MEASUREMENT) feature,
+    /// or the limits set by [`output_limit_bytes`](Perfjail::output_limit_bytes),
+    /// [`stack_limit_kibibytes`](Perfjail::stack_limit_kibibytes) and [`cpu_time_backstop`](Perfjail::cpu_time_backstop).
+    ///
+    /// Note that some limits, like `RLIMIT_NPROC`, apply to all the processes of the user running the child,
+    /// and not only to the processes created by the child.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::ExitStatus::OK;
+    /// use perfjail::process::{Perfjail, Resource};
+    ///
+    /// let result = Perfjail::new("sh")
+    ///     .args(["-c", "test $(ulimit -n) = 64 && test $(ulimit -c) = 0"])
+    ///     .rlimit(Resource::RLIMIT_NOFILE, 64, 64)
+    ///     .rlimit(Resource::RLIMIT_CORE, 0, 0)
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run sh");
+    ///
+    /// assert_eq!(result.exit_status, OK);
+    /// ```
+    pub fn rlimit(mut self, resource: Resource, soft: u64, hard: u64) -> Perfjail {
+        self.rlimits.insert(resource, (soft, hard));
+        self
+    }
+
+    /// Sets a limit on the size of the files the child program can write to (including its standard output
+    /// and standard error, if they are redirected to files). If the child tries to write past the limit,
+    /// it is killed and [`ExitStatus::OLE`](crate::process::ExitStatus::OLE) is returned as the exit status.
+    ///
+    /// The limit is enforced by the kernel through `RLIMIT_FSIZE`, so it only applies to regular files,
+    /// and not to pipes or terminals. To limit output written to a pipe, use [`OutputPump::limit_bytes`] instead.
+    /// It also applies to every file separately, with writes to files
+    /// that were already larger than the limit failing as well.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use perfjail::process::ExitStatus::OLE;
+    /// use perfjail::process::Perfjail;
+    ///
+    /// let output = File::create("/tmp/perfjail_output_limit_example.out").expect("failed to create output file");
+    /// let result = Perfjail::new("yes")
+    ///     .stdout(output)
+    ///     .output_limit_bytes(1024 * 1024) // 1 MiB
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run yes");
+    ///
+    /// assert_eq!(result.exit_status, OLE("output limit exceeded".into()));
+    /// ```
+    pub fn output_limit_bytes(mut self, limit: u64) -> Perfjail {
+        self.output_limit_bytes = Some(limit);
+        self
+    }
+
+    /// Sets a limit on how many threads and processes the child program can create and have running at
+    /// the same time (not counting its main thread). If the limit is exceeded, the child is killed and
+    /// [`ExitStatus::RV`](crate::process::ExitStatus::RV) is returned as the exit status.
+    ///
+    /// A limit of zero only allows the child program to run in a single thread. The threads and processes
+    /// are tracked with ptrace, which requires the child to be run in a new process group, which it can't leave
+    /// (`setpgid` and `setsid` fail with `EPERM`).
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::ExitStatus::RV;
+    /// use perfjail::process::Perfjail;
+    ///
+    /// let result = Perfjail::new("sh")
+    ///     .args(["-c", "true & wait"])
+    ///     .thread_limit(0)
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run sh");
+    ///
+    /// assert_eq!(result.exit_status, RV("thread limit exceeded".into()));
+    /// ```
+    pub fn thread_limit(mut self, limit: u32) -> Perfjail {
+        self.thread_limit = Some(limit);
+        self
+    }
+
+    /// Restricts the child program to running on the CPUs with the given indices, which is applied with
+    /// `sched_setaffinity` right before the program is executed. The threads and processes created by
+    /// the child inherit the restriction.
+    ///
+    /// Pinning the child to a single CPU prevents the scheduler from migrating it between CPUs, which makes
+    /// the [`instructions_used`](crate::process::ExecutionResult::instructions_used) and
+    /// [`user_time`](crate::process::ExecutionResult::user_time) measurements more stable. If none of the CPUs
+    /// exist, [`JailedChild::run`] returns an error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the indices is too large for a CPU set (at least 1024).
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::ExitStatus::OK;
+    /// use perfjail::process::Perfjail;
+    ///
+    /// let result = Perfjail::new("sh")
+    ///     .args(["-c", "test $(nproc) = 1"])
+    ///     .cpu_affinity([0])
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run sh");
+    ///
+    /// assert_eq!(result.exit_status, OK);
+    /// ```
+    pub fn cpu_affinity<I: IntoIterator<Item = usize>>(mut self, cpus: I) -> Perfjail {
+        self.cpu_affinity = Some(cpu_set(cpus));
+        self
+    }
+
+    /// Restricts the thread supervising the child (the one calling [`JailedChild::run`]) to running on the
+    /// CPUs with the given indices while the child is running. The previous affinity of the thread is
+    /// restored once [`run`](JailedChild::run) returns.
+    ///
+    /// When many children run in parallel, pinning each child and its supervisor to different CPUs keeps
+    /// the supervisors from interrupting the measured programs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the indices is too large for a CPU set (at least 1024).
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::Perfjail;
+    ///
+    /// Perfjail::new("ls")
+    ///     .cpu_affinity([0])
+    ///     .supervisor_cpu_affinity([0])
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run ls");
+    /// ```
+    pub fn supervisor_cpu_affinity<I: IntoIterator<Item = usize>>(mut self, cpus: I) -> Perfjail {
+        self.supervisor_cpu_affinity = Some(cpu_set(cpus));
+        self
+    }
+
+    /// Sets the nice value of the child program, from -20 (the highest priority) to 19 (the lowest priority).
+    /// If this function is not called, the nice value is inherited from the parent process.
+    ///
+    /// Setting a value lower than the inherited one requires the `CAP_SYS_NICE` capability or a high enough
+    /// `RLIMIT_NICE`, otherwise [`JailedChild::run`] returns an error.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::ExitStatus::OK;
+    /// use perfjail::process::Perfjail;
+    ///
+    /// let result = Perfjail::new("sh")
+    ///     .args(["-c", "test $(nice) = 19"])
+    ///     .nice(19)
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run sh");
+    ///
+    /// assert_eq!(result.exit_status, OK);
+    /// ```
+    pub fn nice(mut self, level: i32) -> Perfjail {
+        self.nice = Some(level);
+        self
+    }
+
+    /// Sets the scheduling policy of the child program. If this function is not called,
+    /// the scheduling policy is inherited from the parent process.
+    ///
+    /// Running programs which aren't measured (like compilers) with [`SchedulingPolicy::BATCH`] or
+    /// [`SchedulingPolicy::IDLE`] keeps them from taking CPU time away from measured programs.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::{Perfjail, SchedulingPolicy};
+    ///
+    /// Perfjail::new("ls")
+    ///     .scheduling_policy(SchedulingPolicy::BATCH)
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run ls");
+    /// ```
+    pub fn scheduling_policy(mut self, policy: SchedulingPolicy) -> Perfjail {
+        self.scheduling_policy = Some(policy);
+        self
+    }
+
+    /// Sets the I/O priority of the child program, which is applied with `ioprio_set`. If this function is
+    /// not called, the I/O priority is inherited from the parent process.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::{IoPriority, Perfjail};
+    ///
+    /// Perfjail::new("ls")
+    ///     .io_priority(IoPriority::IDLE)
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run ls");
+    /// ```
+    pub fn io_priority(mut self, priority: IoPriority) -> Perfjail {
+        self.io_priority = Some(priority);
+        self
+    }
+
+    /// Sets the policy deciding which system calls the child program is allowed to make.
+    /// If the child makes a system call the policy forbids, it is killed and
+    /// [`ExitStatus::RV`](crate::process::ExitStatus::RV) is returned as the exit status, with the details of
+    /// the system call in [`ExecutionResult::syscall_violation`](crate::process::ExecutionResult::syscall_violation).
+    ///
+    /// Setting a syscall policy also automatically enables the [`SECCOMP`](Feature::SECCOMP)
+    /// feature flag, working the same way as if it was added using the [`features`](Perfjail::features) method.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::Perfjail;
+    /// use perfjail::seccomp::{SeccompAction, SyscallPolicy};
+    ///
+    /// let result = Perfjail::new("mkdir")
+    ///     .arg("/tmp/perfjail_syscall_policy_example")
+    ///     .syscall_policy(SyscallPolicy::new(SeccompAction::ALLOW).rule("mkdir", SeccompAction::KILL))
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run mkdir");
+    ///
+    /// let violation = result.syscall_violation.as_ref().expect("mkdir wasn't forbidden");
+    /// assert_eq!(violation.name, Some("mkdir"));
+    /// assert_eq!(violation.rule.as_deref(), Some("kill mkdir"));
+    /// assert!(result.exit_status.get_exit_status_comment().starts_with("intercepted forbidden syscall mkdir("));
+    /// ```
+    pub fn syscall_policy(mut self, policy: SyscallPolicy) -> Perfjail {
+        self.syscall_policy = Some(policy);
+        self = self.features(Feature::SECCOMP);
+        self
+    }
+
+    /// Sets the handler deciding what happens to system calls matching a
+    /// [`TRACE`](crate::seccomp::SeccompAction::TRACE) rule of the syscall policy.
+    /// If no handler is set, such system calls are allowed.
+    ///
+    /// The handler is only called if a policy containing `TRACE` rules was set with
+    /// [`syscall_policy`](Perfjail::syscall_policy).
+    ///
+    /// # Security
+    ///
+    /// Allowed system calls are executed by the kernel after the handler returns, reading their pointer
+    /// arguments (such as paths) from the memory of the child again. Another thread of the child can change
+    /// that memory between the check and the execution, so the data pointer arguments point to must never
+    /// be used for security decisions, like only allowing some paths to be opened. Only the decisions based
+    /// on the system call number and the values of the arguments themselves (such as flags) can be relied on.
+    /// See [`SyscallHandler`] for details.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::ExitReason;
+    /// use perfjail::process::Perfjail;
+    /// use perfjail::seccomp::{SeccompAction, SyscallDecision, SyscallPolicy};
+    ///
+    /// let result = Perfjail::new("sh")
+    ///     .args(["-c", "echo hello > /dev/null"])
+    ///     .syscall_policy(SyscallPolicy::new(SeccompAction::ALLOW).rule("openat", SeccompAction::TRACE))
+    ///     .syscall_handler(|_, args: &[u64; 6], _| {
+    ///         // Only allow opening files for reading, based on the flags (and not the path) passed to openat
+    ///         if args[2] as libc::c_int & libc::O_ACCMODE == libc::O_RDONLY {
+    ///             SyscallDecision::ALLOW
+    ///         } else {
+    ///             SyscallDecision::ERRNO(libc::EACCES)
+    ///         }
+    ///     })
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run sh");
+    ///
+    /// assert_ne!(result.exit_reason, ExitReason::Exited { exit_status: 0 });
+    /// ```
+    pub fn syscall_handler<H: SyscallHandler + Send + 'static>(mut self, handler: H) -> Perfjail {
+        self.syscall_handler = Some(Box::new(handler));
+        self
+    }
+
+    /// Spawns the child process and runs it, capturing its standard output and standard error into memory.
+    ///
+    /// Streams which are inherited and have no [`OutputPump`] are captured whole. To limit how much of a stream
+    /// is kept, set its pump with [`stdout_pump`](Perfjail::stdout_pump) or [`stderr_pump`](Perfjail::stderr_pump)
+    /// and the limit with [`OutputPump::capture`]. Streams set to anything other than [`Stdio::Inherit`] aren't
+    /// captured, and are returned empty.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::ExitStatus::OK;
+    /// use perfjail::process::{OutputPump, Perfjail};
+    ///
+    /// let output = Perfjail::new("sh")
+    ///     .args(["-c", "echo hello; echo error >&2"])
+    ///     .stderr_pump(OutputPump::new().capture(3))
+    ///     .output()
+    ///     .expect("failed to run sh");
+    ///
+    /// assert_eq!(output.result.exit_status, OK);
+    /// assert_eq!(output.stdout, b"hello\n");
+    /// assert_eq!((output.stderr.as_slice(), output.stderr_truncated), (&b"err"[..], true));
+    /// ```
+    pub fn output(mut self) -> io::Result<Output> {
+        if self.stdout_pump.is_none() && matches!(self.stdout, Stdio::Inherit) {
+            self.stdout_pump = Some(OutputPump::new().capture(usize::MAX));
+        }
+        if self.stderr_pump.is_none() && matches!(self.stderr, Stdio::Inherit) {
+            self.stderr_pump = Some(OutputPump::new().capture(usize::MAX));
+        }
+
+        self.spawn()?.run_with_output()
+    }
+
     /// Spawns the child process used for the execution of the program, returning a handle to it.
     ///
     /// Note that this does not start the execution of the program and instead just spawns the child process preparing for its execution, waiting for it to start until [`JailedChild::run`](JailedChild::run) is run.
@@ -554,24 +1388,54 @@ impl<'a> Perfjail<'a> {
     ///     .run()
     ///     .expect("failed to run ls");
     /// ```
-    pub fn spawn(self) -> io::Result<JailedChild<'a>> {
-        let listeners: Vec<Box<dyn Listener>> = self
+    pub fn spawn(mut self) -> io::Result<JailedChild> {
+        if self.features.contains(Feature::SECCOMP) && self.syscall_policy.is_none() {
+            self.syscall_policy = Some(SyscallPolicy::sio2jail_default());
+        }
+        let seccomp_filter = match self.syscall_policy.as_ref() {
+            Some(policy) => Some(SeccompFilter::compile(policy)?),
+            // Every thread and process of the child is traced, so they have to be kept in its process group
+            None if self.thread_limit.is_some() || self.features.contains(Feature::SYSCALL_AUDIT) => {
+                SeccompFilter::compile_process_group_guard()
+            }
+            None => None,
+        };
+        let home_dir = self.fresh_home.then(TempDir::new).transpose()?;
+        if let Some(home_dir) = home_dir.as_ref() {
+            for key in ["HOME", "TMPDIR"] {
+                self.env_vars.insert(key.into(), Some(home_dir.path().into()));
+            }
+        }
+
+        let requires_ptrace =
+            self.features.contains(Feature::MEMORY_MEASUREMENT) ||
+            self.features.contains(Feature::SECCOMP) ||
+            self.features.contains(Feature::SYSCALL_AUDIT) ||
+            self.thread_limit.is_some();
+        let audit_syscalls = self.features.contains(Feature::SYSCALL_AUDIT);
+        let syscall_handler = self.syscall_handler.take().map(|handler| Arc::new(Mutex::new(handler)));
+        let mut listeners: Vec<Box<dyn Listener>> = self
             .features
             .iter()
-            .map(|feature| match feature {
+            .flat_map(|feature| match feature {
                 Feature::PERF => vec![Box::new(PerfListener::new()) as Box<dyn Listener>],
                 Feature::TIME_MEASUREMENT => vec![Box::new(TimeListener::new()) as Box<dyn Listener>],
-                Feature::MEMORY_MEASUREMENT => vec![
-                    Box::new(MemoryListener::new()) as Box<dyn Listener>,
-                    Box::new(PtraceListener::new()) as Box<dyn Listener>
-                ],
+                Feature::MEMORY_MEASUREMENT => vec![Box::new(MemoryListener::new()) as Box<dyn Listener>],
+                Feature::SECCOMP | Feature::SYSCALL_AUDIT => vec![],
             })
-            .flatten()
+            .chain(requires_ptrace.then(|| Box::new(PtraceListener::new(syscall_handler, audit_syscalls)) as Box<dyn Listener>))
             .collect();
+        if self.stdout_pump.is_some() || self.stderr_pump.is_some() {
+            listeners.push(Box::new(OutputListener::new(self.stdout_pump.take(), self.stderr_pump.take())?));
+        }
+
+        let stdin = mem::replace(&mut self.stdin, Stdio::Inherit).open(STDIN_FILENO)?;
+        let stdout = mem::replace(&mut self.stdout, Stdio::Inherit).open(STDOUT_FILENO)?;
+        let stderr = mem::replace(&mut self.stderr, Stdio::Inherit).open(STDERR_FILENO)?;
 
         let mut context = Box::new(ExecutionContext {
-            settings: ExecutionSettings::new(self),
-            data: ExecutionData::new(),
+            settings: ExecutionSettings::new(self)?,
+            data: ExecutionData::new(seccomp_filter, home_dir, [stdin.child, stdout.child, stderr.child]),
             listeners,
         });
 
@@ -586,6 +1450,9 @@ impl<'a> Perfjail<'a> {
             cvt_no_errno(pthread_attr_destroy(&mut attr as _))?;
 
             context.data.child_ready_barrier.wait();
+            // The child has already moved its standard streams into place, so the parent's copies can be closed,
+            // letting the ends of the pipes in the parent see the child closing them
+            context.data.stdio_fds = Default::default();
 
             assert_ne!(context.data.raw_pid_fd, -1);
             context.data.pid_fd = Some(OwnedFd::from_raw_fd(context.data.raw_pid_fd));
@@ -604,6 +1471,16 @@ impl<'a> Perfjail<'a> {
             );
         }
 
-        Ok(JailedChild::new(context))
+        // The child only installs the filter after the parent is ready, so it's safe to modify it here
+        if let Some(seccomp_filter) = context.data.seccomp_filter.as_mut() {
+            seccomp_filter.set_child_pid(context.data.pid.expect("child pid not set"));
+        }
+
+        Ok(JailedChild::new(
+            context,
+            stdin.parent.map(PipeWriter::from),
+            stdout.parent.map(PipeReader::from),
+            stderr.parent.map(PipeReader::from),
+        ))
     }
 }
@@ -1,12 +1,26 @@
 pub(crate) mod child;
 pub(crate) mod data;
 pub(crate) mod execution_result;
+pub(crate) mod input;
 pub(crate) mod jail;
+pub(crate) mod output;
+pub(crate) mod scheduling;
+pub(crate) mod stdio;
 pub(crate) mod timeout;
 
 pub use self::child::JailedChild;
 pub use self::execution_result::ExecutionResult;
 pub use self::execution_result::ExitReason;
 pub use self::execution_result::ExitStatus;
+pub use self::execution_result::OutputDigest;
+pub use self::execution_result::SyscallCount;
+pub use self::execution_result::SyscallViolation;
+pub use self::input::SharedInput;
 pub use self::jail::Feature;
 pub use self::jail::Perfjail;
+pub use self::output::Output;
+pub use self::output::OutputPump;
+pub use self::scheduling::IoPriority;
+pub use self::scheduling::SchedulingPolicy;
+pub use self::stdio::Stdio;
+pub use nix::sys::resource::{Resource, RLIM_INFINITY};
@@ -0,0 +1,176 @@
+use crate::process::ExecutionResult;
+use std::fs::File;
+
+/// Configures how perfjail reads an output stream of the child program (its standard output or standard error),
+/// which is set with [`Perfjail::stdout_pump`](crate::process::Perfjail::stdout_pump) or
+/// [`Perfjail::stderr_pump`](crate::process::Perfjail::stderr_pump).
+///
+/// The stream is redirected to a pipe, which the supervisor reads from while the child is running.
+/// The data is hashed as it's read, and the SHA-256 digest of the whole stream is returned in the
+/// [`ExecutionResult`](crate::process::ExecutionResult), so outputs can be compared without storing them.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use perfjail::process::{OutputPump, Perfjail};
+///
+/// let result = Perfjail::new("echo")
+///     .arg("hello")
+///     .stdout_pump(OutputPump::new())
+///     .spawn()
+///     .expect("failed to spawn child")
+///     .run()
+///     .expect("failed to run echo");
+///
+/// let digest = result.stdout_digest.as_ref().expect("stdout wasn't read");
+/// assert_eq!(digest.length, 6);
+/// assert_eq!(digest.sha256_hex(), "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03");
+/// ```
+#[derive(Debug, Default)]
+pub struct OutputPump {
+    pub(crate) limit_bytes: Option<u64>,
+    pub(crate) tee: Option<File>,
+    pub(crate) capture_limit_bytes: Option<usize>,
+}
+
+/// The output of a finished child program, returned by [`Perfjail::output`](crate::process::Perfjail::output)
+/// and [`JailedChild::run_with_output`](crate::process::JailedChild::run_with_output).
+///
+/// Only the streams captured by an [`OutputPump`] with [`capture`](OutputPump::capture) set are included,
+/// the others are left empty.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Output {
+    /// The result of the execution of the child program.
+    pub result: ExecutionResult,
+    /// The data the child program has written to its standard output, up to the capture limit.
+    pub stdout: Vec<u8>,
+    /// The data the child program has written to its standard error, up to the capture limit.
+    pub stderr: Vec<u8>,
+    /// Whether the child program has written more than the capture limit to its standard output,
+    /// in which case [`stdout`](Output::stdout) only contains the beginning of it.
+    pub stdout_truncated: bool,
+    /// Whether the child program has written more than the capture limit to its standard error,
+    /// in which case [`stderr`](Output::stderr) only contains the beginning of it.
+    pub stderr_truncated: bool,
+}
+
+/// The beginning of an output stream kept in memory by an [`OutputPump`].
+#[derive(Debug, Default)]
+pub(crate) struct CapturedOutput {
+    pub(crate) bytes: Vec<u8>,
+    pub(crate) truncated: bool,
+    limit_bytes: usize,
+}
+
+impl CapturedOutput {
+    pub(crate) fn new(limit_bytes: usize) -> CapturedOutput {
+        CapturedOutput {
+            bytes: Vec::new(),
+            truncated: false,
+            limit_bytes,
+        }
+    }
+
+    /// Keeps as much of `data` as fits within the limit, marking the output as truncated if it doesn't fit whole.
+    pub(crate) fn push(&mut self, data: &[u8]) {
+        let kept = data.len().min(self.limit_bytes - self.bytes.len());
+        self.bytes.extend_from_slice(&data[..kept]);
+        self.truncated |= kept < data.len();
+    }
+}
+
+impl OutputPump {
+    /// Constructs a new `OutputPump` which only hashes the stream, without limiting its length
+    /// or writing it anywhere.
+    pub fn new() -> OutputPump {
+        OutputPump::default()
+    }
+
+    /// Sets a limit on how many bytes the child program can write to the stream. If the child
+    /// writes more, it is killed and [`ExitStatus::OLE`](crate::process::ExitStatus::OLE) is
+    /// returned as the exit status.
+    ///
+    /// Unlike [`Perfjail::output_limit_bytes`](crate::process::Perfjail::output_limit_bytes), this limit
+    /// is enforced by the supervisor, so it works with pipes. Only the bytes within the limit are
+    /// hashed and written to the tee file.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::ExitStatus::OLE;
+    /// use perfjail::process::{OutputPump, Perfjail};
+    ///
+    /// let result = Perfjail::new("yes")
+    ///     .stdout_pump(OutputPump::new().limit_bytes(1024 * 1024)) // 1 MiB
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run yes");
+    ///
+    /// assert_eq!(result.exit_status, OLE("output limit exceeded".into()));
+    /// assert_eq!(result.stdout_digest.as_ref().unwrap().length, 1024 * 1024);
+    /// ```
+    pub fn limit_bytes(mut self, limit: u64) -> OutputPump {
+        self.limit_bytes = Some(limit);
+        self
+    }
+
+    /// Sets a file every byte read from the stream is also written to, as it's read.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use std::fs::File;
+    /// use perfjail::process::{OutputPump, Perfjail};
+    ///
+    /// let output = File::create("/tmp/perfjail_tee_example.out").expect("failed to create output file");
+    /// Perfjail::new("echo")
+    ///     .arg("hello")
+    ///     .stdout_pump(OutputPump::new().tee(output))
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run echo");
+    ///
+    /// assert_eq!(std::fs::read_to_string("/tmp/perfjail_tee_example.out").unwrap(), "hello\n");
+    /// ```
+    pub fn tee(mut self, file: File) -> OutputPump {
+        self.tee = Some(file);
+        self
+    }
+
+    /// Keeps the first `limit_bytes` bytes read from the stream in memory, so they can be returned in the
+    /// [`Output`] of [`Perfjail::output`](crate::process::Perfjail::output) or
+    /// [`JailedChild::run_with_output`](crate::process::JailedChild::run_with_output).
+    ///
+    /// Unlike [`limit_bytes`](OutputPump::limit_bytes), reaching this limit doesn't affect the child program.
+    /// The rest of the stream is still read and hashed, but it isn't kept, and the output is marked as truncated.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::{OutputPump, Perfjail};
+    ///
+    /// let output = Perfjail::new("echo")
+    ///     .arg("hello world")
+    ///     .stdout_pump(OutputPump::new().capture(5))
+    ///     .output()
+    ///     .expect("failed to run echo");
+    ///
+    /// assert_eq!(output.stdout, b"hello");
+    /// assert!(output.stdout_truncated);
+    /// assert_eq!(output.result.stdout_digest.as_ref().unwrap().length, 12);
+    /// ```
+    pub fn capture(mut self, limit_bytes: usize) -> OutputPump {
+        self.capture_limit_bytes = Some(limit_bytes);
+        self
+    }
+}
@@ -0,0 +1,74 @@
+use cvt::cvt;
+use libc::{c_int, sched_param, sched_setscheduler, syscall, SYS_ioprio_set, SCHED_BATCH, SCHED_IDLE, SCHED_OTHER};
+use std::io;
+
+/// The `which` argument of `ioprio_set` selecting a single process (or thread).
+const IOPRIO_WHO_PROCESS: c_int = 1;
+/// The number of bits the class of an I/O priority is shifted by.
+const IOPRIO_CLASS_SHIFT: c_int = 13;
+
+/// The scheduling policy the child program runs with, set with
+/// [`Perfjail::scheduling_policy`](crate::process::Perfjail::scheduling_policy).
+///
+/// See `sched(7)` for a detailed description of the policies.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SchedulingPolicy {
+    /// The default time-sharing policy (`SCHED_OTHER`).
+    OTHER,
+    /// A policy for non-interactive, CPU-intensive programs (`SCHED_BATCH`), which are
+    /// slightly disfavored in scheduling decisions.
+    BATCH,
+    /// A policy for programs which should only run when nothing else wants the CPU (`SCHED_IDLE`).
+    /// The nice value is ignored under this policy.
+    IDLE,
+}
+
+/// The I/O priority the child program runs with, set with
+/// [`Perfjail::io_priority`](crate::process::Perfjail::io_priority).
+///
+/// See `ioprio_set(2)` for a detailed description of the classes.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IoPriority {
+    /// The real-time class, with a priority level from 0 (highest) to 7 (lowest).
+    /// Setting it requires the `CAP_SYS_ADMIN` capability.
+    REALTIME(u8),
+    /// The best-effort class, with a priority level from 0 (highest) to 7 (lowest).
+    BEST_EFFORT(u8),
+    /// The idle class, which only gets disk time when no other program needs it.
+    IDLE,
+}
+
+impl SchedulingPolicy {
+    /// Sets the scheduling policy of the calling thread.
+    pub(crate) fn apply(self) -> io::Result<()> {
+        let policy = match self {
+            SchedulingPolicy::OTHER => SCHED_OTHER,
+            SchedulingPolicy::BATCH => SCHED_BATCH,
+            SchedulingPolicy::IDLE => SCHED_IDLE,
+        };
+        // The static priority has to be zero for all the non-real-time policies
+        let param = sched_param { sched_priority: 0 };
+        cvt(unsafe { sched_setscheduler(0, policy, &param) })?;
+
+        Ok(())
+    }
+}
+
+impl IoPriority {
+    /// Sets the I/O priority of the calling thread.
+    pub(crate) fn apply(self) -> io::Result<()> {
+        let (class, level) = match self {
+            IoPriority::REALTIME(level) => (1, level),
+            IoPriority::BEST_EFFORT(level) => (2, level),
+            IoPriority::IDLE => (3, 0),
+        };
+        if level > 7 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "I/O priority levels range from 0 to 7"));
+        }
+        cvt(unsafe { syscall(SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, (class << IOPRIO_CLASS_SHIFT) | level as c_int) })?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,288 @@
+use crate::util::sealed_memfd;
+use cvt::cvt_r;
+use libc::{
+    c_void, iovec, msghdr, recvmsg, sendmsg, CMSG_DATA, CMSG_FIRSTHDR, CMSG_LEN, CMSG_SPACE, MSG_CMSG_CLOEXEC,
+    SCM_RIGHTS, SOL_SOCKET, STDIN_FILENO, STDOUT_FILENO,
+};
+use std::ffi::c_int;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{PipeReader, PipeWriter};
+use std::mem::{size_of, zeroed};
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{ChildStderr, ChildStdin, ChildStdout, Command};
+
+/// Describes what to do with a standard stream of the child program, set with
+/// [`Perfjail::stdin`](crate::process::Perfjail::stdin), [`Perfjail::stdout`](crate::process::Perfjail::stdout)
+/// and [`Perfjail::stderr`](crate::process::Perfjail::stderr).
+///
+/// This is the equivalent of [`std::process::Stdio`]. Every variant owns what it refers to, so configuring the
+/// streams doesn't tie the [`Perfjail`](crate::process::Perfjail) builder or the
+/// [`JailedChild`](crate::process::JailedChild) to the lifetime of any file descriptor.
+///
+/// `Stdio` can be created from a [`std::process::Stdio`], as well as from everything a [`std::process::Stdio`]
+/// can be created from, such as [`File`], [`OwnedFd`] and the [`ChildStdout`] of another process. It also has
+/// the same constructors ([`inherit`](Stdio::inherit), [`null`](Stdio::null) and [`piped`](Stdio::piped)),
+/// so code using [`std::process::Stdio`] works with `Stdio` as well.
+///
+/// # Examples
+///
+/// Basic usage:
+///
+/// ```
+/// use perfjail::process::{Perfjail, Stdio};
+/// use std::process::Command;
+///
+/// let generator = Command::new("echo")
+///     .arg("hello")
+///     .stdout(std::process::Stdio::piped())
+///     .spawn()
+///     .expect("failed to spawn echo");
+///
+/// Perfjail::new("cat")
+///     .stdin(generator.stdout.expect("echo's stdout wasn't piped"))
+///     .stdout(Stdio::Null)
+///     .spawn()
+///     .expect("failed to spawn child")
+///     .run()
+///     .expect("failed to run cat");
+/// ```
+#[derive(Debug)]
+pub enum Stdio {
+    /// The stream is inherited from the parent process.
+    Inherit,
+    /// The stream is connected to `/dev/null`.
+    Null,
+    /// The stream is connected to a pipe, the other end of which is available in the
+    /// [`stdin`](crate::process::JailedChild::stdin), [`stdout`](crate::process::JailedChild::stdout)
+    /// or [`stderr`](crate::process::JailedChild::stderr) field of the [`JailedChild`](crate::process::JailedChild).
+    Piped,
+    /// The stream is connected to the file descriptor.
+    File(OwnedFd),
+    /// The stream is connected to the file at the path, which is opened for reading if it's stdin,
+    /// or created (and truncated if it exists) otherwise. The file is opened when the child is spawned.
+    Path(PathBuf),
+    /// The stream reads the data, which is written to a sealed file in memory (a memfd) when the child is spawned.
+    /// Only stdin can be connected to data, as spawning a child with its stdout or stderr connected to it fails.
+    Bytes(Vec<u8>),
+    /// The stream is connected to what the [`std::process::Stdio`] connects it to, with the other end of the pipe
+    /// available in the [`JailedChild`](crate::process::JailedChild) if it's [`piped`](std::process::Stdio::piped).
+    ///
+    /// As [`std::process::Stdio`] doesn't expose what it's configured with, this is found out when the child is
+    /// spawned, by spawning a short-lived helper process with it, which sends the descriptor of the stream back
+    /// instead of executing a program.
+    Std(std::process::Stdio),
+}
+
+/// The ends of the file descriptor a standard stream is connected to.
+#[derive(Debug)]
+pub(crate) struct OpenedStdio {
+    /// The descriptor dup'ed onto the stream in the child, or [`None`] if the stream is inherited.
+    pub(crate) child: Option<OwnedFd>,
+    /// The other end of the pipe, if the stream is [`Stdio::Piped`].
+    pub(crate) parent: Option<OwnedFd>,
+}
+
+impl Stdio {
+    /// The stream is inherited from the parent process, the same as [`Stdio::Inherit`].
+    pub fn inherit() -> Stdio {
+        Stdio::Inherit
+    }
+
+    /// The stream is connected to `/dev/null`, the same as [`Stdio::Null`].
+    pub fn null() -> Stdio {
+        Stdio::Null
+    }
+
+    /// The stream is connected to a pipe, the same as [`Stdio::Piped`].
+    pub fn piped() -> Stdio {
+        Stdio::Piped
+    }
+
+    /// Opens the file descriptors the standard stream `stream` (one of `STDIN_FILENO`, `STDOUT_FILENO`
+    /// and `STDERR_FILENO`) is connected to.
+    pub(crate) fn open(self, stream: RawFd) -> io::Result<OpenedStdio> {
+        let input = stream == STDIN_FILENO;
+        let (child, parent) = match self {
+            Stdio::Inherit => (None, None),
+            Stdio::Null => (Some(open_file(Path::new("/dev/null"), input)?), None),
+            Stdio::Piped => {
+                let (read, write) = io::pipe()?;
+                let (read, write) = (OwnedFd::from(read), OwnedFd::from(write));
+                if input { (Some(read), Some(write)) } else { (Some(write), Some(read)) }
+            }
+            Stdio::File(fd) => (Some(fd), None),
+            Stdio::Path(path) => (Some(open_file(&path, input)?), None),
+            Stdio::Bytes(bytes) if input => (Some(sealed_memfd(c"perfjail-stdin", bytes.as_slice())?.into()), None),
+            Stdio::Bytes(_) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "only stdin can be connected to data"));
+            }
+            Stdio::Std(stdio) => return open_std(stdio, stream),
+        };
+
+        Ok(OpenedStdio { child, parent })
+    }
+}
+
+fn open_file(path: &Path, input: bool) -> io::Result<OwnedFd> {
+    let file = if input {
+        File::open(path)?
+    } else {
+        OpenOptions::new().write(true).create(true).truncate(true).open(path)?
+    };
+
+    Ok(file.into())
+}
+
+/// Finds out what `stdio` connects the standard stream `stream` to, by spawning a helper process with it.
+/// Instead of executing a program, the helper sends the descriptor of the stream back through a socket and exits.
+fn open_std(stdio: std::process::Stdio, stream: RawFd) -> io::Result<OpenedStdio> {
+    let (receiver, sender) = UnixStream::pair()?;
+    let raw_sender = sender.as_raw_fd();
+    let mut command = Command::new("/");
+    match stream {
+        STDIN_FILENO => command.stdin(stdio),
+        STDOUT_FILENO => command.stdout(stdio),
+        _ => command.stderr(stdio),
+    };
+    // The closure is run in the forked helper once its streams are set up, so it can't allocate memory
+    unsafe {
+        command.pre_exec(move || {
+            send_fd(raw_sender, stream);
+            libc::_exit(0)
+        });
+    }
+
+    let mut helper = command.spawn()?;
+    drop(sender);
+    let child = receive_fd(&receiver);
+    // Waiting for the helper closes its stdin, so the other end of the pipe has to be taken before
+    let parent = helper.stdin.take().map(OwnedFd::from)
+        .or_else(|| helper.stdout.take().map(OwnedFd::from))
+        .or_else(|| helper.stderr.take().map(OwnedFd::from));
+    helper.wait()?;
+
+    Ok(OpenedStdio { child: Some(child?), parent })
+}
+
+/// The buffer for the control message of [`send_fd`] and [`receive_fd`], aligned for a `cmsghdr`.
+type FdControlBuffer = [u64; 4];
+
+/// Fills in `message` so it carries one byte of data from `byte` and a control message in `control`
+/// large enough for a single file descriptor.
+fn prepare_fd_message(message: &mut msghdr, iov: &mut iovec, byte: &mut u8, control: &mut FdControlBuffer) {
+    iov.iov_base = byte as *mut u8 as *mut c_void;
+    iov.iov_len = 1;
+    message.msg_iov = iov;
+    message.msg_iovlen = 1;
+    message.msg_control = control.as_mut_ptr() as *mut c_void;
+    message.msg_controllen = unsafe { CMSG_SPACE(size_of::<c_int>() as u32) } as _;
+}
+
+/// Sends `fd` through the Unix socket `socket`. This doesn't allocate memory, as it's called in a forked process.
+fn send_fd(socket: RawFd, fd: RawFd) {
+    unsafe {
+        let (mut message, mut iov, mut byte, mut control): (msghdr, iovec, u8, FdControlBuffer) = zeroed();
+        prepare_fd_message(&mut message, &mut iov, &mut byte, &mut control);
+        let header = CMSG_FIRSTHDR(&message);
+        (*header).cmsg_level = SOL_SOCKET;
+        (*header).cmsg_type = SCM_RIGHTS;
+        (*header).cmsg_len = CMSG_LEN(size_of::<c_int>() as u32) as _;
+        *(CMSG_DATA(header) as *mut c_int) = fd;
+        // If sending fails, the receiving end finds no descriptor in the message and reports the error
+        sendmsg(socket, &message, 0);
+    }
+}
+
+/// Receives a file descriptor sent with [`send_fd`] through the Unix socket `socket`.
+fn receive_fd(socket: &UnixStream) -> io::Result<OwnedFd> {
+    unsafe {
+        let (mut message, mut iov, mut byte, mut control): (msghdr, iovec, u8, FdControlBuffer) = zeroed();
+        prepare_fd_message(&mut message, &mut iov, &mut byte, &mut control);
+        cvt_r(|| recvmsg(socket.as_raw_fd(), &mut message, MSG_CMSG_CLOEXEC))?;
+
+        let header = CMSG_FIRSTHDR(&message);
+        if header.is_null() || (*header).cmsg_level != SOL_SOCKET || (*header).cmsg_type != SCM_RIGHTS {
+            return Err(io::Error::other("failed to receive the descriptor of a standard stream"));
+        }
+        Ok(OwnedFd::from_raw_fd(*(CMSG_DATA(header) as *const c_int)))
+    }
+}
+
+impl From<OwnedFd> for Stdio {
+    fn from(fd: OwnedFd) -> Stdio {
+        Stdio::File(fd)
+    }
+}
+
+/// Duplicates the file descriptor, so the stream stays connected to it even after it's closed by its owner.
+///
+/// # Errors
+///
+/// Returns an error if the file descriptor can't be duplicated, for example because the process has run out of them.
+impl TryFrom<BorrowedFd<'_>> for Stdio {
+    type Error = io::Error;
+
+    fn try_from(fd: BorrowedFd<'_>) -> io::Result<Stdio> {
+        Ok(Stdio::File(fd.try_clone_to_owned()?))
+    }
+}
+
+impl From<std::process::Stdio> for Stdio {
+    fn from(stdio: std::process::Stdio) -> Stdio {
+        Stdio::Std(stdio)
+    }
+}
+
+impl From<File> for Stdio {
+    fn from(file: File) -> Stdio {
+        Stdio::File(file.into())
+    }
+}
+
+impl From<PipeReader> for Stdio {
+    fn from(pipe: PipeReader) -> Stdio {
+        Stdio::File(pipe.into())
+    }
+}
+
+impl From<PipeWriter> for Stdio {
+    fn from(pipe: PipeWriter) -> Stdio {
+        Stdio::File(pipe.into())
+    }
+}
+
+impl From<ChildStdin> for Stdio {
+    fn from(stdin: ChildStdin) -> Stdio {
+        Stdio::File(stdin.into())
+    }
+}
+
+impl From<ChildStdout> for Stdio {
+    fn from(stdout: ChildStdout) -> Stdio {
+        Stdio::File(stdout.into())
+    }
+}
+
+impl From<ChildStderr> for Stdio {
+    fn from(stderr: ChildStderr) -> Stdio {
+        Stdio::File(stderr.into())
+    }
+}
+
+/// Connects the stream to the stdout of the parent process, the same as the [`std::process::Stdio`] created from it.
+impl From<io::Stdout> for Stdio {
+    fn from(stdout: io::Stdout) -> Stdio {
+        Stdio::Std(stdout.into())
+    }
+}
+
+/// Connects the stream to the stderr of the parent process, the same as the [`std::process::Stdio`] created from it.
+impl From<io::Stderr> for Stdio {
+    fn from(stderr: io::Stderr) -> Stdio {
+        Stdio::Std(stderr.into())
+    }
+}
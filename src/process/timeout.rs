@@ -7,7 +7,7 @@ use std::{mem, thread};
 use std::time::Duration;
 use cvt::cvt;
 
-static mut PREVIOUS_SIGHANDLER: *mut libc::sigaction = 0 as *mut _;
+static mut PREVIOUS_SIGHANDLER: *mut libc::sigaction = std::ptr::null_mut();
 static TIMEOUT_THREAD_DATA: LazyLock<Mutex<LinearSet<pid_t>>> = LazyLock::new(|| Mutex::new(LinearSet::new()));
 static TIMEOUT_THREAD: Once = Once::new();
 
@@ -29,7 +29,7 @@ fn init_timeout_thread() {
 
     unsafe {
         let mut sigusr1: libc::sigaction = zeroed();
-        sigusr1.sa_sigaction = sigusr1_handler as usize;
+        sigusr1.sa_sigaction = sigusr1_handler as *const () as usize;
         sigusr1.sa_flags = libc::SA_SIGINFO;
         PREVIOUS_SIGHANDLER = Box::into_raw(Box::new(zeroed()));
         cvt(libc::sigaction(SIGUSR1, &sigusr1, PREVIOUS_SIGHANDLER)).expect("failed to set SIGUSR1 handler");
@@ -37,7 +37,7 @@ fn init_timeout_thread() {
 }
 
 pub(crate) fn add_timeout_thread() {
-    TIMEOUT_THREAD.call_once(|| init_timeout_thread());
+    TIMEOUT_THREAD.call_once(init_timeout_thread);
     TIMEOUT_THREAD_DATA.lock().expect("failed to lock TIMEOUT_THREAD_DATA").insert(unsafe { gettid() });
 }
 
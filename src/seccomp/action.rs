@@ -1,10 +1,34 @@
-enum SeccompAction {
+use libc::{c_int, SECCOMP_RET_ALLOW, SECCOMP_RET_DATA, SECCOMP_RET_ERRNO, SECCOMP_RET_USER_NOTIF};
+
+/// The action taken when a system call made by the child program matches a rule of a
+/// [`SyscallPolicy`](crate::seccomp::SyscallPolicy).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SeccompAction {
+    /// The system call is executed normally.
     ALLOW,
+    /// The child waits while the system call is reported to the supervising process, which
+    /// decides what happens to it using the [`SyscallHandler`](crate::seccomp::SyscallHandler) set with
+    /// [`Perfjail::syscall_handler`](crate::process::Perfjail::syscall_handler).
+    /// If no handler was set, the system call is executed normally.
     TRACE,
-    ERRNO,
-    KILL
+    /// The system call is not executed and instead fails with the given `errno` value
+    /// (for example [`libc::EPERM`]). A value of zero makes it return 0, as if it had succeeded.
+    ERRNO(c_int),
+    /// The child program is killed and [`ExitStatus::RV`](crate::process::ExitStatus::RV)
+    /// is returned as the exit status.
+    KILL,
 }
 
 impl SeccompAction {
-    
-}
\ No newline at end of file
+    /// Returns the seccomp return value representing this action in a BPF filter.
+    ///
+    /// Both [`TRACE`](SeccompAction::TRACE) and [`KILL`](SeccompAction::KILL) are handled by
+    /// the supervising process, which is notified about them through the listener of the filter.
+    pub(crate) fn to_seccomp_return_value(self) -> u32 {
+        match self {
+            SeccompAction::ALLOW => SECCOMP_RET_ALLOW,
+            SeccompAction::TRACE | SeccompAction::KILL => SECCOMP_RET_USER_NOTIF,
+            SeccompAction::ERRNO(errno) => SECCOMP_RET_ERRNO | (errno as u32 & SECCOMP_RET_DATA),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use libc::{pid_t, seccomp_data, sock_filter, BPF_ALU, BPF_AND, BPF_JEQ, BPF_JGE, BPF_JGT, BPF_K};
+use std::mem::offset_of;
+
+use crate::seccomp::filter::{jump, load};
+
+/// A condition on one of the arguments of a system call, which has to hold for a rule added with
+/// [`SyscallPolicy::rule_if`](crate::seccomp::SyscallPolicy::rule_if) to match.
+///
+/// Arguments are indexed from zero and compared as unsigned 64-bit integers, after being
+/// masked with the mask of the condition (which keeps all the bits unless set otherwise).
+///
+/// # Examples
+///
+/// ```
+/// use perfjail::seccomp::ArgumentCondition;
+///
+/// // The file is opened with `openat` for reading only
+/// let read_only = ArgumentCondition::masked_equal(2, libc::O_ACCMODE as u64, libc::O_RDONLY as u64);
+/// // The signal is sent by `kill` to the child itself
+/// let to_itself = ArgumentCondition::child_pid(0);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ArgumentCondition {
+    pub(crate) argument: usize,
+    pub(crate) mask: u64,
+    pub(crate) comparison: Comparison,
+    pub(crate) value: ConditionValue,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Comparison {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum ConditionValue {
+    Constant(u64),
+    /// The pid of the child process, which is only known once it has been spawned.
+    ChildPid,
+}
+
+/// The placeholder value of an offset for a jump taken when a condition doesn't hold.
+///
+/// It's replaced with the real offset once the code for the whole rule has been generated.
+pub(crate) const FAIL_JUMP_PLACEHOLDER: u8 = u8::MAX;
+
+impl ArgumentCondition {
+    fn new(argument: usize, mask: u64, comparison: Comparison, value: ConditionValue) -> ArgumentCondition {
+        assert!(argument < 6, "System calls only have 6 arguments, got argument index {argument}");
+
+        ArgumentCondition {
+            argument,
+            mask,
+            comparison,
+            value,
+        }
+    }
+
+    /// Creates a condition holding if the argument with index `argument` is equal to `value`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `argument` is not smaller than 6.
+    pub fn equal(argument: usize, value: u64) -> ArgumentCondition {
+        ArgumentCondition::new(argument, u64::MAX, Comparison::Equal, ConditionValue::Constant(value))
+    }
+
+    /// Creates a condition holding if the argument with index `argument` is not equal to `value`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `argument` is not smaller than 6.
+    pub fn not_equal(argument: usize, value: u64) -> ArgumentCondition {
+        ArgumentCondition::new(argument, u64::MAX, Comparison::NotEqual, ConditionValue::Constant(value))
+    }
+
+    /// Creates a condition holding if the argument with index `argument` is greater than `value`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `argument` is not smaller than 6.
+    pub fn greater(argument: usize, value: u64) -> ArgumentCondition {
+        ArgumentCondition::new(argument, u64::MAX, Comparison::Greater, ConditionValue::Constant(value))
+    }
+
+    /// Creates a condition holding if the argument with index `argument` is greater than or equal to `value`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `argument` is not smaller than 6.
+    pub fn greater_or_equal(argument: usize, value: u64) -> ArgumentCondition {
+        ArgumentCondition::new(argument, u64::MAX, Comparison::GreaterOrEqual, ConditionValue::Constant(value))
+    }
+
+    /// Creates a condition holding if the argument with index `argument` is less than `value`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `argument` is not smaller than 6.
+    pub fn less(argument: usize, value: u64) -> ArgumentCondition {
+        ArgumentCondition::new(argument, u64::MAX, Comparison::Less, ConditionValue::Constant(value))
+    }
+
+    /// Creates a condition holding if the argument with index `argument` is less than or equal to `value`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `argument` is not smaller than 6.
+    pub fn less_or_equal(argument: usize, value: u64) -> ArgumentCondition {
+        ArgumentCondition::new(argument, u64::MAX, Comparison::LessOrEqual, ConditionValue::Constant(value))
+    }
+
+    /// Creates a condition holding if the bits of the argument with index `argument` selected by
+    /// `mask` are equal to `value`. This is mostly useful for checking flags.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `argument` is not smaller than 6.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use perfjail::seccomp::ArgumentCondition;
+    ///
+    /// // The memory mapped by `mmap` is not executable
+    /// let not_executable = ArgumentCondition::masked_equal(2, libc::PROT_EXEC as u64, 0);
+    /// ```
+    pub fn masked_equal(argument: usize, mask: u64, value: u64) -> ArgumentCondition {
+        ArgumentCondition::new(argument, mask, Comparison::Equal, ConditionValue::Constant(value & mask))
+    }
+
+    /// Creates a condition holding if the argument with index `argument` is the pid of the child
+    /// process (comparing only its lower 32 bits, as pids are 32-bit integers).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `argument` is not smaller than 6.
+    pub fn child_pid(argument: usize) -> ArgumentCondition {
+        ArgumentCondition::new(argument, u32::MAX as u64, Comparison::Equal, ConditionValue::ChildPid)
+    }
+
+    /// Returns whether the condition holds for the given system call arguments.
+    pub(crate) fn evaluate(&self, args: &[u64; 6], child_pid: pid_t) -> bool {
+        let argument = args[self.argument] & self.mask;
+        let value = self.resolve_value(child_pid);
+
+        match self.comparison {
+            Comparison::Equal => argument == value,
+            Comparison::NotEqual => argument != value,
+            Comparison::Greater => argument > value,
+            Comparison::GreaterOrEqual => argument >= value,
+            Comparison::Less => argument < value,
+            Comparison::LessOrEqual => argument <= value,
+        }
+    }
+
+    fn resolve_value(&self, child_pid: pid_t) -> u64 {
+        match self.value {
+            ConditionValue::Constant(value) => value,
+            ConditionValue::ChildPid => child_pid as u32 as u64,
+        }
+    }
+
+    /// Generates BPF code which falls through if the condition holds, and otherwise jumps with
+    /// an offset of [`FAIL_JUMP_PLACEHOLDER`].
+    ///
+    /// Child pid values are generated as zero, and have to be patched in once the pid is known.
+    /// The returned indices point to the instructions which have to be patched.
+    pub(crate) fn compile(&self) -> (Vec<sock_filter>, Vec<usize>) {
+        let value = self.resolve_value(0);
+        let (low_mask, high_mask) = (self.mask as u32, (self.mask >> 32) as u32);
+        let (low_value, high_value) = (value as u32, (value >> 32) as u32);
+        let low_offset = offset_of!(seccomp_data, args) + self.argument * 8;
+        let high_offset = low_offset + 4;
+
+        let mut code = Vec::new();
+        const FAIL: u8 = FAIL_JUMP_PLACEHOLDER;
+
+        // Each comparison is split into a comparison of the upper and lower 32 bits,
+        // as BPF only operates on 32-bit values
+        let mut pid_indices = Vec::new();
+        match self.comparison {
+            Comparison::Equal => {
+                if high_mask != 0 {
+                    load_masked(&mut code, high_offset, high_mask);
+                    code.push(jump(BPF_JEQ, high_value, 0, FAIL));
+                }
+                load_masked(&mut code, low_offset, low_mask);
+                pid_indices.push(code.len());
+                code.push(jump(BPF_JEQ, low_value, 0, FAIL));
+            }
+            Comparison::NotEqual => {
+                load_masked(&mut code, low_offset, low_mask);
+                let high_length = 2 + (high_mask != u32::MAX) as u8;
+                code.push(jump(BPF_JEQ, low_value, 0, high_length));
+                load_masked(&mut code, high_offset, high_mask);
+                code.push(jump(BPF_JEQ, high_value, FAIL, 0));
+            }
+            Comparison::Greater | Comparison::GreaterOrEqual => {
+                load_masked(&mut code, high_offset, high_mask);
+                let low_length = 2 + (low_mask != u32::MAX) as u8;
+                code.push(jump(BPF_JGT, high_value, low_length + 1, 0));
+                code.push(jump(BPF_JEQ, high_value, 0, FAIL));
+                load_masked(&mut code, low_offset, low_mask);
+                let low_jump = if self.comparison == Comparison::Greater { BPF_JGT } else { BPF_JGE };
+                code.push(jump(low_jump, low_value, 0, FAIL));
+            }
+            Comparison::Less | Comparison::LessOrEqual => {
+                load_masked(&mut code, high_offset, high_mask);
+                let low_length = 2 + (low_mask != u32::MAX) as u8;
+                code.push(jump(BPF_JGT, high_value, FAIL, 0));
+                code.push(jump(BPF_JEQ, high_value, 0, low_length));
+                load_masked(&mut code, low_offset, low_mask);
+                let low_jump = if self.comparison == Comparison::Less { BPF_JGE } else { BPF_JGT };
+                code.push(jump(low_jump, low_value, FAIL, 0));
+            }
+        }
+
+        if self.value != ConditionValue::ChildPid {
+            pid_indices.clear();
+        }
+        (code, pid_indices)
+    }
+}
+
+fn load_masked(code: &mut Vec<sock_filter>, offset: usize, mask: u32) {
+    code.push(load(offset));
+    if mask != u32::MAX {
+        code.push(and(mask));
+    }
+}
+
+fn and(mask: u32) -> sock_filter {
+    sock_filter {
+        code: (BPF_ALU | BPF_AND | BPF_K) as u16,
+        jt: 0,
+        jf: 0,
+        k: mask,
+    }
+}
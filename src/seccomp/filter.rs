@@ -0,0 +1,252 @@
+use crate::seccomp::action::SeccompAction;
+use crate::seccomp::condition::FAIL_JUMP_PLACEHOLDER;
+use crate::seccomp::policy::SyscallPolicy;
+use crate::seccomp::syscalls::{syscall_number, SyscallAbi, AUDIT_ARCH_I386, AUDIT_ARCH_NATIVE, X32_SYSCALL_BIT};
+use cvt::cvt;
+use libc::{
+    pid_t, prctl, seccomp_data, sock_filter, sock_fprog, syscall, SYS_seccomp, BPF_ABS, BPF_JA,
+    BPF_JEQ, BPF_JMP, BPF_JSET, BPF_K, BPF_LD, BPF_RET, BPF_W, EPERM, PR_SET_NO_NEW_PRIVS,
+    SECCOMP_FILTER_FLAG_NEW_LISTENER, SECCOMP_RET_TRACE, SECCOMP_RET_USER_NOTIF, SECCOMP_SET_MODE_FILTER,
+};
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::io::ErrorKind::{InvalidInput, Unsupported};
+use std::mem::offset_of;
+use std::os::fd::RawFd;
+
+/// A seccomp BPF program compiled from a [`SyscallPolicy`], ready to be installed in the child process.
+pub(crate) struct SeccompFilter {
+    program: Vec<sock_filter>,
+    /// Indices of the instructions comparing arguments with the pid of the child process.
+    child_pid_indices: Vec<usize>,
+}
+
+impl SeccompFilter {
+    /// Compiles `policy` into a BPF program.
+    ///
+    /// The program always reports `execve` and `execveat` to the supervisor with a ptrace stop, as it needs to
+    /// allow the initial execution of the child program regardless of the policy and start listening
+    /// for the notifications of the filter before that. Later executions, including the ones made by
+    /// the processes the child creates, which are traced as well, are checked against the policy by the supervisor.
+    /// System calls made through ABIs without a policy
+    /// are reported to the supervisor with a notification, so it can kill the child and report the violation.
+    /// `setpgid` and `setsid` always fail with `EPERM`, so the child can't leave its process group.
+    pub(crate) fn compile(policy: &SyscallPolicy) -> io::Result<SeccompFilter> {
+        let Some(native_arch) = AUDIT_ARCH_NATIVE else {
+            return Err(io::Error::new(Unsupported, "seccomp is not supported on this architecture"));
+        };
+
+        let mut filter = SeccompFilter {
+            program: vec![load(offset_of!(seccomp_data, arch)), jump(BPF_JEQ, native_arch, 1, 0)],
+            child_pid_indices: Vec::new(),
+        };
+        // The native section is usually too long for a conditional jump over it
+        let foreign_jump_index = filter.program.len();
+        filter.program.push(jump_always(0));
+
+        filter.program.push(load(offset_of!(seccomp_data, nr)));
+        // x32 system calls are reported with the native architecture, but with a different bit set in their number
+        filter.program.push(jump(BPF_JSET, X32_SYSCALL_BIT, 0, 1));
+        filter.program.push(ret(SECCOMP_RET_USER_NOTIF));
+        filter.program.extend(process_group_guard(SyscallAbi::NATIVE, 0));
+        for exec_syscall in ["execve", "execveat"] {
+            filter.program.push(jump(BPF_JEQ, policy_syscall_number(policy, exec_syscall), 0, 1));
+            filter.program.push(ret(SECCOMP_RET_TRACE));
+        }
+        filter.compile_rules(policy)?;
+
+        // The accumulator still holds the architecture when jumping here
+        filter.program[foreign_jump_index].k = (filter.program.len() - foreign_jump_index - 1) as u32;
+        if let Some(i386_policy) = policy.i386_policy.as_deref() {
+            filter.program.push(jump(BPF_JEQ, AUDIT_ARCH_I386, 1, 0));
+            filter.program.push(ret(SECCOMP_RET_USER_NOTIF));
+            filter.program.push(load(offset_of!(seccomp_data, nr)));
+            filter.program.extend(process_group_guard(SyscallAbi::I386, 0));
+            filter.compile_rules(i386_policy)?;
+        } else {
+            filter.program.push(ret(SECCOMP_RET_USER_NOTIF));
+        }
+
+        if filter.program.len() > u16::MAX as usize {
+            return Err(io::Error::new(InvalidInput, "the compiled seccomp filter is too long"));
+        }
+
+        Ok(filter)
+    }
+
+    /// Compiles a BPF program which only keeps the child from leaving its process group, for when every thread
+    /// and process of the child is traced, but there's no policy to compile. Returns [`None`] if seccomp
+    /// isn't supported on this architecture.
+    pub(crate) fn compile_process_group_guard() -> Option<SeccompFilter> {
+        let native_arch = AUDIT_ARCH_NATIVE?;
+        let allow = ret(SeccompAction::ALLOW.to_seccomp_return_value());
+
+        let mut native_section = vec![load(offset_of!(seccomp_data, nr))];
+        native_section.extend(process_group_guard(SyscallAbi::NATIVE, 0));
+        native_section.extend(process_group_guard(SyscallAbi::NATIVE, X32_SYSCALL_BIT));
+        native_section.push(allow);
+        let mut i386_section = vec![load(offset_of!(seccomp_data, nr))];
+        i386_section.extend(process_group_guard(SyscallAbi::I386, 0));
+        i386_section.push(allow);
+
+        let mut program = vec![
+            load(offset_of!(seccomp_data, arch)),
+            jump(BPF_JEQ, native_arch, 0, native_section.len() as u8),
+        ];
+        program.extend(native_section);
+        program.push(jump(BPF_JEQ, AUDIT_ARCH_I386, 0, i386_section.len() as u8));
+        program.extend(i386_section);
+        program.push(allow);
+
+        Some(SeccompFilter { program, child_pid_indices: Vec::new() })
+    }
+
+    /// Appends the rules and the default action of `policy` to the program, which has to have
+    /// the system call number loaded into the accumulator at that point.
+    fn compile_rules(&mut self, policy: &SyscallPolicy) -> io::Result<()> {
+        for rule in &policy.rules {
+            let syscall_number = policy_syscall_number(policy, &rule.syscall);
+            let action = rule.action.to_seccomp_return_value();
+            if rule.conditions.is_empty() {
+                self.program.push(jump(BPF_JEQ, syscall_number, 0, 1));
+                self.program.push(ret(action));
+                continue;
+            }
+
+            // The conditions overwrite the accumulator, so the system call number has to be
+            // loaded again after them for the following rules
+            let mut condition_code = Vec::new();
+            for condition in &rule.conditions {
+                let (code, pid_indices) = condition.compile();
+                let rule_start = self.program.len() + 1 + condition_code.len();
+                self.child_pid_indices.extend(pid_indices.into_iter().map(|pid_index| rule_start + pid_index));
+                condition_code.extend(code);
+            }
+            let Ok(conditions_length) = u8::try_from(condition_code.len() + 1) else {
+                return Err(io::Error::new(InvalidInput, "a syscall rule has too many argument conditions"));
+            };
+
+            let conditions_end = condition_code.len();
+            for (offset, instruction) in condition_code.iter_mut().enumerate() {
+                // Jump over the return to the instruction reloading the system call number
+                let fail_offset = (conditions_end - offset) as u8;
+                if instruction.jt == FAIL_JUMP_PLACEHOLDER {
+                    instruction.jt = fail_offset;
+                }
+                if instruction.jf == FAIL_JUMP_PLACEHOLDER {
+                    instruction.jf = fail_offset;
+                }
+            }
+
+            self.program.push(jump(BPF_JEQ, syscall_number, 0, conditions_length));
+            self.program.extend(condition_code);
+            self.program.push(ret(action));
+            self.program.push(load(offset_of!(seccomp_data, nr)));
+        }
+
+        self.program.push(ret(policy.default_action.to_seccomp_return_value()));
+        Ok(())
+    }
+
+    /// Fills in the pid of the child process in conditions comparing arguments with it.
+    ///
+    /// This has to be called after the child is spawned, but before it installs the filter.
+    pub(crate) fn set_child_pid(&mut self, pid: pid_t) {
+        for &index in &self.child_pid_indices {
+            self.program[index].k = pid as u32;
+        }
+    }
+
+    /// Installs the filter for the calling thread, returning the file descriptor of its notification listener.
+    ///
+    /// This is meant to be called in the child process right before the execution of the child program,
+    /// as every system call made afterward is subject to the filter. The listener is closed on execution,
+    /// so it has to be taken over by the supervisor before then.
+    ///
+    /// The supervisor only starts handling notifications at the first execution, so no system call other than
+    /// `execve` can be made between installing the filter and executing the program. Otherwise, a system call
+    /// the policy traces would block forever, so in particular, nothing can allocate memory in between.
+    pub(crate) fn install(&self) -> io::Result<RawFd> {
+        let program = sock_fprog {
+            len: self.program.len() as u16,
+            filter: self.program.as_ptr() as *mut sock_filter,
+        };
+
+        unsafe {
+            cvt(prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+            let listener_fd = cvt(syscall(
+                SYS_seccomp,
+                SECCOMP_SET_MODE_FILTER,
+                SECCOMP_FILTER_FLAG_NEW_LISTENER,
+                &program as *const sock_fprog,
+            ))?;
+
+            Ok(listener_fd as RawFd)
+        }
+    }
+}
+
+impl Debug for SeccompFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SeccompFilter")
+            .field("program_length", &self.program.len())
+            .finish()
+    }
+}
+
+fn policy_syscall_number(policy: &SyscallPolicy, name: &str) -> u32 {
+    syscall_number(policy.abi, name).expect("syscall names should be validated when building the policy") as u32
+}
+
+pub(crate) fn load(offset: usize) -> sock_filter {
+    sock_filter {
+        code: (BPF_LD | BPF_W | BPF_ABS) as u16,
+        jt: 0,
+        jf: 0,
+        k: offset as u32,
+    }
+}
+
+/// Creates a conditional jump comparing the accumulator with `value` using `operation`
+/// (one of `BPF_JEQ`, `BPF_JGT`, `BPF_JGE` and `BPF_JSET`).
+pub(crate) fn jump(operation: u32, value: u32, jump_true: u8, jump_false: u8) -> sock_filter {
+    sock_filter {
+        code: (BPF_JMP | operation | BPF_K) as u16,
+        jt: jump_true,
+        jf: jump_false,
+        k: value,
+    }
+}
+
+/// Creates an unconditional jump over `offset` instructions.
+fn jump_always(offset: u32) -> sock_filter {
+    sock_filter {
+        code: (BPF_JMP | BPF_JA) as u16,
+        jt: 0,
+        jf: 0,
+        k: offset,
+    }
+}
+
+/// Creates the instructions making `setpgid` and `setsid` fail with `EPERM`, for the system call numbers of `abi`
+/// with `number_bits` set, which have to be loaded into the accumulator.
+///
+/// The supervisor waits for the threads and processes of the child and kills them using its process group,
+/// so they can't be allowed to leave it, regardless of the policy.
+fn process_group_guard(abi: SyscallAbi, number_bits: u32) -> Vec<sock_filter> {
+    ["setpgid", "setsid"].into_iter()
+        .flat_map(|syscall| {
+            let number = syscall_number(abi, syscall).expect("every ABI should have setpgid and setsid") as u32;
+            [jump(BPF_JEQ, number | number_bits, 0, 1), ret(SeccompAction::ERRNO(EPERM).to_seccomp_return_value())]
+        })
+        .collect()
+}
+
+fn ret(value: u32) -> sock_filter {
+    sock_filter {
+        code: (BPF_RET | BPF_K) as u16,
+        jt: 0,
+        jf: 0,
+        k: value,
+    }
+}
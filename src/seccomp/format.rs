@@ -0,0 +1,126 @@
+use crate::seccomp::condition::{ArgumentCondition, Comparison, ConditionValue};
+use crate::seccomp::policy::SyscallRule;
+use crate::seccomp::{SeccompAction, SyscallPolicy};
+use libc::c_int;
+use std::fmt::{Display, Formatter};
+
+/// The names of errors which can be used in `errno` actions, along with their values.
+static ERRNO_NAMES: &[(&str, c_int)] = &[
+    ("EPERM", libc::EPERM),
+    ("ENOENT", libc::ENOENT),
+    ("ESRCH", libc::ESRCH),
+    ("EINTR", libc::EINTR),
+    ("EIO", libc::EIO),
+    ("ENXIO", libc::ENXIO),
+    ("E2BIG", libc::E2BIG),
+    ("ENOEXEC", libc::ENOEXEC),
+    ("EBADF", libc::EBADF),
+    ("ECHILD", libc::ECHILD),
+    ("EAGAIN", libc::EAGAIN),
+    ("ENOMEM", libc::ENOMEM),
+    ("EACCES", libc::EACCES),
+    ("EFAULT", libc::EFAULT),
+    ("EBUSY", libc::EBUSY),
+    ("EEXIST", libc::EEXIST),
+    ("EXDEV", libc::EXDEV),
+    ("ENODEV", libc::ENODEV),
+    ("ENOTDIR", libc::ENOTDIR),
+    ("EISDIR", libc::EISDIR),
+    ("EINVAL", libc::EINVAL),
+    ("ENFILE", libc::ENFILE),
+    ("EMFILE", libc::EMFILE),
+    ("ENOTTY", libc::ENOTTY),
+    ("EFBIG", libc::EFBIG),
+    ("ENOSPC", libc::ENOSPC),
+    ("ESPIPE", libc::ESPIPE),
+    ("EROFS", libc::EROFS),
+    ("EPIPE", libc::EPIPE),
+    ("ERANGE", libc::ERANGE),
+    ("ENOSYS", libc::ENOSYS),
+    ("EOPNOTSUPP", libc::EOPNOTSUPP),
+    ("EAFNOSUPPORT", libc::EAFNOSUPPORT),
+    ("ECONNREFUSED", libc::ECONNREFUSED),
+    ("ENETUNREACH", libc::ENETUNREACH),
+];
+
+fn errno_name(errno: c_int) -> Option<&'static str> {
+    ERRNO_NAMES
+        .iter()
+        .find(|(_, value)| *value == errno)
+        .map(|(name, _)| *name)
+}
+
+/// Returns the value of the error called `name`, or [`None`] if it can't be used in `errno` actions.
+pub(crate) fn errno_value(name: &str) -> Option<c_int> {
+    ERRNO_NAMES
+        .iter()
+        .find(|(errno_name, _)| *errno_name == name)
+        .map(|(_, value)| *value)
+}
+
+impl Display for SeccompAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SeccompAction::ALLOW => write!(f, "allow"),
+            SeccompAction::TRACE => write!(f, "trace"),
+            SeccompAction::ERRNO(errno) => match errno_name(*errno) {
+                Some(name) => write!(f, "errno({name})"),
+                None => write!(f, "errno({errno})"),
+            },
+            SeccompAction::KILL => write!(f, "kill"),
+        }
+    }
+}
+
+impl Display for ArgumentCondition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let masked = self.mask != u64::MAX && self.value != ConditionValue::ChildPid;
+        write!(f, "arg{}", self.argument)?;
+        if masked {
+            write!(f, " & {:#x}", self.mask)?;
+        }
+
+        let comparison = match self.comparison {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+        };
+        match self.value {
+            ConditionValue::ChildPid => write!(f, " {comparison} child_pid"),
+            ConditionValue::Constant(value) if masked => write!(f, " {comparison} {value:#x}"),
+            ConditionValue::Constant(value) => write!(f, " {comparison} {value}"),
+        }
+    }
+}
+
+impl Display for SyscallRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.action, self.syscall)?;
+        for (index, condition) in self.conditions.iter().enumerate() {
+            write!(f, " {} {condition}", if index == 0 { "if" } else { "and" })?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats the policy in the text format of policy files, which is described in the documentation of
+/// [`SyscallPolicy::load`].
+impl Display for SyscallPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "default {}", self.default_action)?;
+
+        for rule in &self.rules {
+            writeln!(f, "{rule}")?;
+        }
+
+        if let Some(i386_policy) = self.i386_policy.as_ref() {
+            writeln!(f, "abi {}", i386_policy.abi)?;
+            write!(f, "{i386_policy}")?;
+        }
+
+        Ok(())
+    }
+}
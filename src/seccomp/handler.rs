@@ -0,0 +1,145 @@
+use libc::{c_int, c_long, pid_t};
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::sync::{Arc, Mutex};
+
+use crate::seccomp::action::SeccompAction;
+
+/// A [`SyscallHandler`] shared between the threads supervising the child.
+pub(crate) type SharedSyscallHandler = Arc<Mutex<Box<dyn SyscallHandler + Send>>>;
+
+/// The maximum length of a string read by [`read_child_string`], including the terminating null byte.
+const MAX_CHILD_STRING_LENGTH: usize = libc::PATH_MAX as usize;
+
+/// A handler deciding what happens to system calls matching a [`TRACE`](crate::seccomp::SeccompAction::TRACE)
+/// rule of a [`SyscallPolicy`](crate::seccomp::SyscallPolicy), set with
+/// [`Perfjail::syscall_handler`](crate::process::Perfjail::syscall_handler).
+///
+/// The handler is called by the supervising process while the thread making the system call waits for the decision,
+/// so it can inspect the memory of the child (for example with [`read_child_string`]) before deciding.
+///
+/// `SyscallHandler` is implemented for all closures taking the same arguments as [`handle`](SyscallHandler::handle).
+///
+/// # Security
+///
+/// When the handler allows a system call, the kernel executes it after the handler returns, reading its pointer
+/// arguments from the memory of the child only then. The other threads of the child keep running in the meantime,
+/// so they can change the data a pointer argument points to after the handler has checked it (a time-of-check
+/// to time-of-use race). For example, a handler allowing `openat` only for `/dev/urandom` can be bypassed by
+/// a thread rewriting the path right after the check.
+///
+/// The data pointer arguments point to (read with [`read_child_string`] or otherwise) must therefore never be used
+/// for deciding to allow a system call. It can still be used for diagnostics, or for denying a system call with
+/// [`ERRNO`](SyscallDecision::ERRNO) or [`KILL`](SyscallDecision::KILL), as a denied system call isn't executed.
+/// The system call number and the values of the arguments are copied by the kernel, so decisions based
+/// on them alone are safe.
+///
+/// # Examples
+///
+/// ```
+/// use libc::{c_long, pid_t};
+/// use perfjail::seccomp::{SyscallDecision, SyscallHandler};
+///
+/// /// Counts the traced system calls, allowing all of them
+/// #[derive(Default)]
+/// struct CountingHandler {
+///     count: usize,
+/// }
+///
+/// impl SyscallHandler for CountingHandler {
+///     fn handle(&mut self, _: c_long, _: &[u64; 6], _: pid_t) -> SyscallDecision {
+///         self.count += 1;
+///         SyscallDecision::ALLOW
+///     }
+/// }
+/// ```
+pub trait SyscallHandler {
+    /// Decides what happens to the system call with the number `syscall`, called
+    /// with the arguments `args` by the child thread with the thread id `tid`.
+    ///
+    /// The number is native, unless the system call matched a rule of an
+    /// [i386 policy](crate::seccomp::SyscallPolicy::i386_policy).
+    fn handle(&mut self, syscall: c_long, args: &[u64; 6], tid: pid_t) -> SyscallDecision;
+}
+
+impl<F> SyscallHandler for F
+where
+    F: FnMut(c_long, &[u64; 6], pid_t) -> SyscallDecision,
+{
+    fn handle(&mut self, syscall: c_long, args: &[u64; 6], tid: pid_t) -> SyscallDecision {
+        self(syscall, args, tid)
+    }
+}
+
+/// The decision made by a [`SyscallHandler`] about a traced system call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SyscallDecision {
+    /// The system call is executed normally.
+    ALLOW,
+    /// The system call is not executed and instead fails with the given `errno` value
+    /// (for example [`libc::EPERM`]).
+    ERRNO(c_int),
+    /// The child program is killed and [`ExitStatus::RV`](crate::process::ExitStatus::RV)
+    /// is returned as the exit status.
+    KILL,
+}
+
+impl SyscallDecision {
+    /// Returns the decision made about a system call for which a policy takes `action`, calling
+    /// `handler` (if there is one) for [`TRACE`](SeccompAction::TRACE) actions.
+    pub(crate) fn from_action(
+        action: SeccompAction,
+        handler: Option<&SharedSyscallHandler>,
+        syscall: c_long,
+        args: &[u64; 6],
+        tid: pid_t,
+    ) -> SyscallDecision {
+        match action {
+            SeccompAction::ALLOW => SyscallDecision::ALLOW,
+            SeccompAction::TRACE => match handler {
+                Some(handler) => handler.lock()
+                    .expect("Failed to lock syscall_handler")
+                    .handle(syscall, args, tid),
+                None => SyscallDecision::ALLOW,
+            },
+            SeccompAction::ERRNO(errno) => SyscallDecision::ERRNO(errno),
+            SeccompAction::KILL => SyscallDecision::KILL,
+        }
+    }
+}
+
+/// Reads a null-terminated string (like a path passed to `openat`) from the memory of the
+/// child thread with the thread id `tid`, starting at `address`.
+///
+/// This is meant to be called from a [`SyscallHandler`], while the thread is waiting.
+///
+/// # Security
+///
+/// Other threads of the child can modify the string after it has been read, before the system call is executed,
+/// so the string must not be used for deciding to allow the system call. See [`SyscallHandler`] for details.
+///
+/// # Errors
+///
+/// Returns an error if the memory can't be read, or if the string is longer than `PATH_MAX`.
+pub fn read_child_string(tid: pid_t, address: u64) -> io::Result<CString> {
+    let memory = File::open(format!("/proc/{tid}/mem"))?;
+    let mut string = Vec::new();
+    let mut buffer = [0u8; 256];
+
+    while string.len() < MAX_CHILD_STRING_LENGTH {
+        let read = memory.read_at(&mut buffer, address + string.len() as u64)?;
+        if read == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+
+        if let Some(end) = buffer[..read].iter().position(|&byte| byte == 0) {
+            string.extend_from_slice(&buffer[..end]);
+            return Ok(CString::new(string).expect("the string shouldn't contain null bytes"));
+        }
+        string.extend_from_slice(&buffer[..read]);
+    }
+
+    Err(io::Error::new(io::ErrorKind::InvalidData, "the string is longer than PATH_MAX"))
+}
@@ -1 +1,17 @@
-pub(crate) mod action;
\ No newline at end of file
+pub(crate) mod action;
+pub(crate) mod condition;
+pub(crate) mod filter;
+pub(crate) mod format;
+pub(crate) mod handler;
+pub(crate) mod notify;
+pub(crate) mod parse;
+pub(crate) mod policies;
+pub(crate) mod policy;
+pub(crate) mod syscalls;
+
+pub use self::action::SeccompAction;
+pub use self::condition::ArgumentCondition;
+pub use self::handler::{read_child_string, SyscallDecision, SyscallHandler};
+pub use self::parse::PolicyParseError;
+pub use self::policy::SyscallPolicy;
+pub use self::syscalls::SyscallAbi;
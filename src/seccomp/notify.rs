@@ -0,0 +1,190 @@
+use crate::process::SyscallViolation;
+use crate::seccomp::handler::SharedSyscallHandler;
+use crate::seccomp::syscalls::SyscallAbi;
+use crate::seccomp::{SyscallDecision, SyscallPolicy};
+use crate::util::kill_pid;
+use cvt::cvt;
+use libc::{
+    c_long, eventfd, ioctl, pid_t, poll, pollfd, seccomp_notif, seccomp_notif_resp, syscall,
+    SYS_pidfd_getfd, EFD_CLOEXEC, ENOENT, POLLIN, SECCOMP_IOCTL_NOTIF_ID_VALID,
+    SECCOMP_IOCTL_NOTIF_RECV, SECCOMP_IOCTL_NOTIF_SEND, SECCOMP_IOCTL_NOTIF_SET_FLAGS,
+    SECCOMP_USER_NOTIF_FLAG_CONTINUE,
+};
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::mem::zeroed;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+
+/// Makes the kernel wake the supervisor up on the same CPU the child is running on, which
+/// considerably lowers the latency of notifications (supported since Linux 6.6).
+const SECCOMP_USER_NOTIF_FD_SYNC_WAKE_UP: u64 = 1;
+
+/// Handles the seccomp user notifications of the child, which are sent for system calls
+/// matching [`TRACE`](crate::seccomp::SeccompAction::TRACE) and
+/// [`KILL`](crate::seccomp::SeccompAction::KILL) rules.
+///
+/// Unlike ptrace stops, notifications don't require the supervisor to wait for and resume the child,
+/// and they're sent by every thread and process of the child, not just the traced ones.
+/// They're handled on a separate thread, so they're answered even while the supervisor is waiting for the child.
+#[derive(Debug)]
+pub(crate) struct SeccompNotifier {
+    stop_event: File,
+    violation: Arc<Mutex<Option<SyscallViolation>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SeccompNotifier {
+    /// Starts handling the notifications sent to the listener with the file descriptor
+    /// `listener_fd` in the child process referred to by `pid_fd`.
+    pub(crate) fn start(
+        pid_fd: BorrowedFd,
+        listener_fd: RawFd,
+        child_pid: pid_t,
+        policy: SyscallPolicy,
+        handler: Option<SharedSyscallHandler>,
+    ) -> io::Result<SeccompNotifier> {
+        let listener = unsafe {
+            OwnedFd::from_raw_fd(cvt(syscall(SYS_pidfd_getfd, pid_fd.as_raw_fd(), listener_fd, 0))? as RawFd)
+        };
+        let stop_event = unsafe { File::from_raw_fd(cvt(eventfd(0, EFD_CLOEXEC))?) };
+
+        // Older kernels don't support the flag, in which case notifications are just a bit slower
+        let _ = unsafe { ioctl(listener.as_raw_fd(), SECCOMP_IOCTL_NOTIF_SET_FLAGS, SECCOMP_USER_NOTIF_FD_SYNC_WAKE_UP) };
+
+        let violation = Arc::new(Mutex::new(None));
+        let thread_stop_event = stop_event.try_clone()?;
+        let thread_violation = violation.clone();
+        let thread = thread::spawn(move || {
+            let notification_loop = NotificationLoop {
+                listener,
+                stop_event: thread_stop_event,
+                child_pid,
+                policy,
+                handler,
+                violation: thread_violation,
+            };
+
+            // If the listener fails, the child is left blocked on its system call, so it's killed instead
+            if notification_loop.run().is_err() {
+                let _ = kill_pid(child_pid);
+            }
+        });
+
+        Ok(SeccompNotifier {
+            stop_event,
+            violation,
+            thread: Some(thread),
+        })
+    }
+
+    /// Returns the forbidden system call the child was killed for, if there was one.
+    pub(crate) fn take_violation(&self) -> Option<SyscallViolation> {
+        self.violation.lock().expect("Failed to lock violation").take()
+    }
+
+    /// Stops handling notifications, waiting for the handling thread to exit.
+    pub(crate) fn stop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.stop_event.write_all(&1u64.to_ne_bytes()).expect("Failed to write to the stop event");
+            thread.join().expect("The seccomp notification thread panicked");
+        }
+    }
+}
+
+impl Drop for SeccompNotifier {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+struct NotificationLoop {
+    listener: OwnedFd,
+    stop_event: File,
+    child_pid: pid_t,
+    policy: SyscallPolicy,
+    handler: Option<SharedSyscallHandler>,
+    violation: Arc<Mutex<Option<SyscallViolation>>>,
+}
+
+impl NotificationLoop {
+    fn run(&self) -> io::Result<()> {
+        let mut poll_fds = [
+            pollfd { fd: self.listener.as_raw_fd(), events: POLLIN, revents: 0 },
+            pollfd { fd: self.stop_event.as_raw_fd(), events: POLLIN, revents: 0 },
+        ];
+
+        loop {
+            match cvt(unsafe { poll(poll_fds.as_mut_ptr(), poll_fds.len() as _, -1) }) {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+
+            if poll_fds[1].revents != 0 {
+                return Ok(());
+            }
+            if poll_fds[0].revents & POLLIN != 0 {
+                self.handle_notification()?;
+            } else if poll_fds[0].revents != 0 {
+                // The listener was closed, as every process using the filter has exited
+                return Ok(());
+            }
+        }
+    }
+
+    fn handle_notification(&self) -> io::Result<()> {
+        let mut notification: seccomp_notif = unsafe { zeroed() };
+        match cvt(unsafe { ioctl(self.listener.as_raw_fd(), SECCOMP_IOCTL_NOTIF_RECV, &mut notification) }) {
+            Ok(_) => {}
+            // The thread making the system call was killed before the notification was received
+            Err(e) if e.raw_os_error() == Some(ENOENT) => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => return Ok(()),
+            Err(e) => return Err(e),
+        }
+
+        let syscall = notification.data.nr as c_long;
+        let args = &notification.data.args;
+        let tid = notification.pid as pid_t;
+        let abi = SyscallAbi::from_audit_arch(notification.data.arch, syscall);
+        let (action, rule) = self.policy.evaluate(abi, syscall, args, self.child_pid);
+        let decision = SyscallDecision::from_action(action, self.handler.as_ref(), syscall, args, tid);
+
+        // The handler might have taken a while, so the thread could have been killed in the meantime
+        if cvt(unsafe { ioctl(self.listener.as_raw_fd(), SECCOMP_IOCTL_NOTIF_ID_VALID, &notification.id) }).is_err() {
+            return Ok(());
+        }
+
+        let mut response = seccomp_notif_resp {
+            id: notification.id,
+            val: 0,
+            error: 0,
+            flags: 0,
+        };
+        match decision {
+            // The kernel reads the pointer arguments of the system call again when it continues it, so the handler
+            // can't safely allow system calls based on the memory they point to (see the docs of SyscallHandler)
+            SyscallDecision::ALLOW => response.flags = SECCOMP_USER_NOTIF_FLAG_CONTINUE as u32,
+            SyscallDecision::ERRNO(errno) => response.error = -errno,
+            SyscallDecision::KILL => {
+                // The violation has to be saved before the child is killed, so it's there once the child exits
+                self.violation.lock()
+                    .expect("Failed to lock violation")
+                    .get_or_insert_with(|| SyscallViolation::new(abi, syscall, *args, tid, rule.map(ToString::to_string)));
+                let _ = kill_pid(tid);
+                let _ = kill_pid(self.child_pid);
+                return Ok(());
+            }
+        }
+
+        match cvt(unsafe { ioctl(self.listener.as_raw_fd(), SECCOMP_IOCTL_NOTIF_SEND, &mut response) }) {
+            Ok(_) => Ok(()),
+            // The thread making the system call was killed after the notification was received
+            Err(e) if e.raw_os_error() == Some(ENOENT) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::seccomp::format::errno_value;
+use crate::seccomp::syscalls::syscall_number;
+use crate::seccomp::{ArgumentCondition, SeccompAction, SyscallPolicy};
+
+/// An error returned when parsing a policy file fails, see [`SyscallPolicy::load`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PolicyParseError {
+    line: usize,
+    message: String,
+}
+
+impl PolicyParseError {
+    fn new<M: Into<String>>(line: usize, message: M) -> PolicyParseError {
+        PolicyParseError {
+            line,
+            message: message.into(),
+        }
+    }
+
+    /// Returns the number of the line the error occurred on, counting from one.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Returns the description of the error, without the line number.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for PolicyParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for PolicyParseError {}
+
+/// Parses a policy in the text format of policy files, which is described in the documentation of
+/// [`SyscallPolicy::load`].
+impl FromStr for SyscallPolicy {
+    type Err = PolicyParseError;
+
+    fn from_str(s: &str) -> Result<SyscallPolicy, PolicyParseError> {
+        let mut parser = PolicyParser::default();
+        for (index, line) in s.lines().enumerate() {
+            let statement = line.split_once('#').map_or(line, |(statement, _)| statement);
+            let tokens: Vec<&str> = statement.split_whitespace().collect();
+            if !tokens.is_empty() {
+                parser.parse_statement(index + 1, &tokens)?;
+            }
+        }
+
+        parser.finish(s.lines().count().max(1))
+    }
+}
+
+#[derive(Clone)]
+struct ParsedRule {
+    line: usize,
+    syscall: String,
+    conditions: Vec<ArgumentCondition>,
+    action: SeccompAction,
+}
+
+#[derive(Default)]
+struct PolicyParser {
+    groups: HashMap<String, Vec<ParsedRule>>,
+    /// The name, first line and rules of the group being defined.
+    current_group: Option<(String, usize, Vec<ParsedRule>)>,
+    native_policy: Option<SyscallPolicy>,
+    /// The line of the `abi i386` statement and the i386 policy, once its default action is set.
+    i386_section: Option<(usize, Option<SyscallPolicy>)>,
+}
+
+impl PolicyParser {
+    fn parse_statement(&mut self, line: usize, tokens: &[&str]) -> Result<(), PolicyParseError> {
+        match tokens {
+            ["group", name] => {
+                if self.current_group.is_some() {
+                    return Err(PolicyParseError::new(line, "groups can't be nested"));
+                }
+                if !is_valid_group_name(name) {
+                    return Err(PolicyParseError::new(line, format!("invalid group name `{name}`")));
+                }
+                if self.groups.contains_key(*name) {
+                    return Err(PolicyParseError::new(line, format!("group `{name}` is already defined")));
+                }
+                self.current_group = Some((name.to_string(), line, Vec::new()));
+            }
+            ["end"] => {
+                let Some((name, _, rules)) = self.current_group.take() else {
+                    return Err(PolicyParseError::new(line, "`end` without a matching `group`"));
+                };
+                self.groups.insert(name, rules);
+            }
+            ["use", name] => {
+                let Some(rules) = self.groups.get(*name).cloned() else {
+                    return Err(PolicyParseError::new(line, format!("unknown group `{name}`")));
+                };
+                for rule in rules {
+                    self.add_rule(line, rule)?;
+                }
+            }
+            ["default", action] => {
+                self.ensure_outside_group(line, "default")?;
+                let action = parse_action(line, action)?;
+                let (policy, new_policy) = match self.i386_section.as_mut() {
+                    Some((_, i386_policy)) => (i386_policy, SyscallPolicy::new_i386(action)),
+                    None => (&mut self.native_policy, SyscallPolicy::new(action)),
+                };
+                if policy.is_some() {
+                    return Err(PolicyParseError::new(line, "the default action is already set"));
+                }
+                *policy = Some(new_policy);
+            }
+            ["abi", abi] => {
+                self.ensure_outside_group(line, "abi")?;
+                if *abi != "i386" {
+                    return Err(PolicyParseError::new(line, format!("unsupported ABI `{abi}`, only `i386` can have a separate policy")));
+                }
+                if self.native_policy.is_none() {
+                    return Err(PolicyParseError::new(line, "expected a `default` statement before `abi`"));
+                }
+                if self.i386_section.is_some() {
+                    return Err(PolicyParseError::new(line, "the i386 policy is already defined"));
+                }
+                self.i386_section = Some((line, None));
+            }
+            ["group" | "end" | "use" | "default" | "abi", ..] => {
+                return Err(PolicyParseError::new(line, format!("invalid `{}` statement", tokens[0])));
+            }
+            [action, rest @ ..] => {
+                let rule = parse_rule(line, action, rest)?;
+                self.add_rule(line, rule)?;
+            }
+            [] => {}
+        }
+
+        Ok(())
+    }
+
+    fn ensure_outside_group(&self, line: usize, statement: &str) -> Result<(), PolicyParseError> {
+        match self.current_group {
+            Some(_) => Err(PolicyParseError::new(line, format!("`{statement}` can't be used inside a group"))),
+            None => Ok(()),
+        }
+    }
+
+    /// Adds `rule` to the group being defined or to the current policy, which happens on `line`.
+    fn add_rule(&mut self, line: usize, rule: ParsedRule) -> Result<(), PolicyParseError> {
+        if let Some((_, _, rules)) = self.current_group.as_mut() {
+            rules.push(rule);
+            return Ok(());
+        }
+
+        let policy = match self.i386_section.as_mut() {
+            Some((_, i386_policy)) => i386_policy,
+            None => &mut self.native_policy,
+        };
+        let Some(abi) = policy.as_ref().map(|policy| policy.abi) else {
+            return Err(PolicyParseError::new(line, "expected a `default` statement before the first rule"));
+        };
+        if syscall_number(abi, &rule.syscall).is_none() {
+            // Rules from groups are reported on their own line, as that's where the name is
+            return Err(PolicyParseError::new(rule.line, format!("unknown {abi} system call `{}`", rule.syscall)));
+        }
+        *policy = policy.take().map(|policy| policy.rule_if(rule.syscall, rule.conditions, rule.action));
+
+        Ok(())
+    }
+
+    fn finish(self, last_line: usize) -> Result<SyscallPolicy, PolicyParseError> {
+        if let Some((name, line, _)) = self.current_group {
+            return Err(PolicyParseError::new(line, format!("group `{name}` is missing its `end`")));
+        }
+        let Some(policy) = self.native_policy else {
+            return Err(PolicyParseError::new(last_line, "the policy has no `default` statement"));
+        };
+
+        match self.i386_section {
+            Some((_, Some(i386_policy))) => Ok(policy.i386_policy(i386_policy)),
+            Some((line, None)) => Err(PolicyParseError::new(line, "the i386 policy has no `default` statement")),
+            None => Ok(policy),
+        }
+    }
+}
+
+fn is_valid_group_name(name: &str) -> bool {
+    name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn parse_rule(line: usize, action: &str, tokens: &[&str]) -> Result<ParsedRule, PolicyParseError> {
+    let Some(action) = try_parse_action(action) else {
+        return Err(PolicyParseError::new(line, format!("unknown statement or action `{action}`")));
+    };
+    let Some((syscall, mut tokens)) = tokens.split_first() else {
+        return Err(PolicyParseError::new(line, "expected a system call name after the action"));
+    };
+
+    let mut conditions = Vec::new();
+    while let Some((keyword, rest)) = tokens.split_first() {
+        let expected_keyword = if conditions.is_empty() { "if" } else { "and" };
+        if *keyword != expected_keyword {
+            return Err(PolicyParseError::new(line, format!("expected `{expected_keyword}`, found `{keyword}`")));
+        }
+
+        let length = if rest.get(1) == Some(&"&") { 5 } else { 3 };
+        if rest.len() < length {
+            return Err(PolicyParseError::new(line, format!("incomplete condition after `{keyword}`")));
+        }
+        conditions.push(parse_condition(line, &rest[..length])?);
+        tokens = &rest[length..];
+    }
+
+    Ok(ParsedRule {
+        line,
+        syscall: syscall.to_string(),
+        conditions,
+        action,
+    })
+}
+
+fn parse_action(line: usize, action: &str) -> Result<SeccompAction, PolicyParseError> {
+    try_parse_action(action).ok_or_else(|| PolicyParseError::new(line, format!("unknown action `{action}`")))
+}
+
+fn try_parse_action(action: &str) -> Option<SeccompAction> {
+    match action {
+        "allow" => Some(SeccompAction::ALLOW),
+        "trace" => Some(SeccompAction::TRACE),
+        "kill" => Some(SeccompAction::KILL),
+        _ => {
+            let errno = action.strip_prefix("errno(")?.strip_suffix(')')?;
+            errno_value(errno)
+                .or_else(|| errno.parse().ok().filter(|&errno| errno >= 0))
+                .map(SeccompAction::ERRNO)
+        }
+    }
+}
+
+/// Parses a condition in the form of `argN [& mask] op value`, split into tokens.
+fn parse_condition(line: usize, tokens: &[&str]) -> Result<ArgumentCondition, PolicyParseError> {
+    let argument = tokens[0]
+        .strip_prefix("arg")
+        .and_then(|index| index.parse::<usize>().ok())
+        .filter(|&index| index < 6)
+        .ok_or_else(|| PolicyParseError::new(line, format!("expected an argument from `arg0` to `arg5`, found `{}`", tokens[0])))?;
+
+    let (mask, comparison, value) = match tokens {
+        [_, "&", mask, comparison, value] => (Some(parse_number(line, mask)?), *comparison, *value),
+        [_, comparison, value] => (None, *comparison, *value),
+        _ => unreachable!("conditions are split into 3 or 5 tokens"),
+    };
+
+    if value == "child_pid" {
+        return match (mask, comparison) {
+            (None, "==") => Ok(ArgumentCondition::child_pid(argument)),
+            (Some(_), _) => Err(PolicyParseError::new(line, "`child_pid` can't be compared with a masked argument")),
+            _ => Err(PolicyParseError::new(line, "`child_pid` can only be compared using `==`")),
+        };
+    }
+
+    let value = parse_number(line, value)?;
+    match (mask, comparison) {
+        (Some(mask), "==") => Ok(ArgumentCondition::masked_equal(argument, mask, value)),
+        (Some(_), _) => Err(PolicyParseError::new(line, "masked arguments can only be compared using `==`")),
+        (None, "==") => Ok(ArgumentCondition::equal(argument, value)),
+        (None, "!=") => Ok(ArgumentCondition::not_equal(argument, value)),
+        (None, ">") => Ok(ArgumentCondition::greater(argument, value)),
+        (None, ">=") => Ok(ArgumentCondition::greater_or_equal(argument, value)),
+        (None, "<") => Ok(ArgumentCondition::less(argument, value)),
+        (None, "<=") => Ok(ArgumentCondition::less_or_equal(argument, value)),
+        (None, comparison) => Err(PolicyParseError::new(line, format!("unknown comparison `{comparison}`"))),
+    }
+}
+
+/// Parses a decimal or hexadecimal (`0x`-prefixed) number. Negative decimal numbers are
+/// converted to their two's complement representation, as system calls see them.
+fn parse_number(line: usize, number: &str) -> Result<u64, PolicyParseError> {
+    let parsed = if let Some(hex) = number.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()
+    } else if number.starts_with('-') {
+        number.parse::<i64>().ok().map(|number| number as u64)
+    } else {
+        number.parse::<u64>().ok()
+    };
+
+    parsed.ok_or_else(|| PolicyParseError::new(line, format!("invalid number `{number}`")))
+}
@@ -0,0 +1,340 @@
+use std::mem;
+
+use libc::{
+    CLONE_THREAD, EACCES, EINVAL, ENOSYS, ENOTTY, EPERM, MAP_ANONYMOUS, O_ACCMODE, O_CREAT, O_RDONLY,
+    O_TRUNC, PRIO_PROCESS, PROT_EXEC, PR_GET_NAME, PR_SET_NAME,
+};
+
+use crate::seccomp::action::SeccompAction::{ALLOW, ERRNO, KILL};
+use crate::seccomp::condition::ArgumentCondition;
+use crate::seccomp::policy::SyscallPolicy;
+
+/// Matches files opened for reading only, without being created or truncated.
+const READ_ONLY_OPEN_FLAGS: (u64, u64) = ((O_ACCMODE | O_CREAT | O_TRUNC) as u64, O_RDONLY as u64);
+
+impl SyscallPolicy {
+    /// Returns a policy equivalent to the default policy of sio2jail, which allows statically linked
+    /// programs (for example C and C++ solutions compiled with `-static`) to read their input, write
+    /// their output, manage memory and query basic system information, but forbids everything else.
+    /// In particular, executable memory can't be mapped and signals can only be sent to the child itself.
+    ///
+    /// Forbidden system calls that are commonly made by standard libraries just to probe their
+    /// environment (like `ioctl` or `open`) fail with an error instead of killing the child program.
+    ///
+    /// This is the policy used by the [`SECCOMP`](crate::process::Feature::SECCOMP) feature if no
+    /// other policy was set with [`Perfjail::syscall_policy`](crate::process::Perfjail::syscall_policy).
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```no_run
+    /// use perfjail::process::Perfjail;
+    /// use perfjail::seccomp::SyscallPolicy;
+    ///
+    /// let result = Perfjail::new("./static_solution")
+    ///     .syscall_policy(SyscallPolicy::sio2jail_default())
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run the solution");
+    /// ```
+    pub fn sio2jail_default() -> SyscallPolicy {
+        SyscallPolicy::new(KILL)
+            .with_execution_control_rules()
+            .with_non_executable_memory_rules()
+            .with_memory_management_rules()
+            .with_system_information_rules()
+            .with_file_system_rules()
+    }
+
+    /// Returns a policy for C and C++ programs, including dynamically linked ones.
+    ///
+    /// In addition to everything allowed by [`sio2jail_default`](SyscallPolicy::sio2jail_default),
+    /// it allows the child program to map files as executable, which the dynamic linker does with
+    /// shared libraries. Opening the libraries isn't allowed though, as the path of a file can't be
+    /// checked by the filter - dynamically linked programs need the policy to be extended with
+    /// [`allow_reading_files`](SyscallPolicy::allow_reading_files), which lets them read every file
+    /// the user they're run as can read.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::ExitReason;
+    /// use perfjail::process::Perfjail;
+    /// use perfjail::seccomp::SyscallPolicy;
+    ///
+    /// let result = Perfjail::new("true")
+    ///     .syscall_policy(SyscallPolicy::cpp().allow_reading_files())
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run true");
+    ///
+    /// assert_eq!(result.exit_reason, ExitReason::Exited { exit_status: 0 });
+    /// ```
+    pub fn cpp() -> SyscallPolicy {
+        SyscallPolicy::new(KILL)
+            .with_execution_control_rules()
+            .with_non_executable_memory_rules()
+            .with_file_mapping_rules()
+            .with_memory_management_rules()
+            .with_system_information_rules()
+            .with_file_system_rules()
+    }
+
+    /// Returns a policy for Python programs run with the CPython interpreter.
+    ///
+    /// In addition to everything allowed by [`cpp`](SyscallPolicy::cpp), it allows the interpreter to
+    /// look through directories and read its standard library while importing modules. As with
+    /// [`allow_reading_files`](SyscallPolicy::allow_reading_files), **the child program can read every
+    /// file the user it's run as can read**, so it should be run as a user which can't read anything
+    /// the solution shouldn't see (for example the expected outputs of tests).
+    ///
+    /// Note that the interpreter has to be started directly - wrapper scripts like the ones used by
+    /// `pyenv` create new processes, which is forbidden.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```no_run
+    /// use perfjail::process::Perfjail;
+    /// use perfjail::seccomp::SyscallPolicy;
+    ///
+    /// let result = Perfjail::new("/usr/bin/python3")
+    ///     .arg("solution.py")
+    ///     .syscall_policy(SyscallPolicy::python())
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run python3");
+    /// ```
+    pub fn python() -> SyscallPolicy {
+        SyscallPolicy::new(KILL)
+            .with_execution_control_rules()
+            .with_non_executable_memory_rules()
+            .with_file_mapping_rules()
+            .with_memory_management_rules()
+            .with_system_information_rules()
+            .with_file_reading_rules()
+            .with_interpreter_rules()
+            .with_file_system_rules()
+    }
+
+    /// Returns a policy for Java programs run with the HotSpot JVM.
+    ///
+    /// In addition to everything allowed by [`python`](SyscallPolicy::python), it allows the JVM to
+    /// create and synchronize the threads it needs for garbage collection and compilation, and to
+    /// map executable memory for the code it compiles. New processes still can't be created. Like with
+    /// [`python`](SyscallPolicy::python), **every file the user the JVM is run as can read can be read**.
+    ///
+    /// It's recommended to run the JVM with the `-XX:-UsePerfData` option, as otherwise it tries
+    /// to create a performance data file in `/tmp`, which is forbidden.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```no_run
+    /// use perfjail::process::Perfjail;
+    /// use perfjail::seccomp::SyscallPolicy;
+    ///
+    /// let result = Perfjail::new("/usr/bin/java")
+    ///     .args(["-XX:-UsePerfData", "Main"])
+    ///     .syscall_policy(SyscallPolicy::java())
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run java");
+    /// ```
+    pub fn java() -> SyscallPolicy {
+        SyscallPolicy::new(KILL)
+            .with_execution_control_rules()
+            .rules(["mmap", "mprotect"], ALLOW)
+            .with_memory_management_rules()
+            .with_system_information_rules()
+            .with_file_reading_rules()
+            .with_interpreter_rules()
+            .with_threading_rules()
+            .with_file_system_rules()
+    }
+
+    /// Returns an i386 counterpart of [`sio2jail_default`](SyscallPolicy::sio2jail_default), which allows
+    /// statically linked 32-bit programs (for example solutions compiled with `-m32 -static`) to do the same things.
+    ///
+    /// System calls made through the i386 ABI are forbidden unless this (or another i386 policy) is
+    /// explicitly added to a policy with [`i386_policy`](SyscallPolicy::i386_policy).
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```no_run
+    /// use perfjail::process::Perfjail;
+    /// use perfjail::seccomp::SyscallPolicy;
+    ///
+    /// let result = Perfjail::new("./static_32_bit_solution")
+    ///     .syscall_policy(SyscallPolicy::sio2jail_default().i386_policy(SyscallPolicy::sio2jail_i386()))
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run the solution");
+    /// ```
+    pub fn sio2jail_i386() -> SyscallPolicy {
+        let non_executable = ArgumentCondition::masked_equal(2, PROT_EXEC as u64, 0);
+
+        SyscallPolicy::new_i386(KILL)
+            .rules([
+                "restart_syscall", "exit", "exit_group", "set_thread_area", "get_thread_area",
+                "set_tid_address", "set_robust_list", "rseq", "futex", "futex_time64", "getpid", "gettid",
+                "getrandom", "getrlimit", "ugetrlimit", "rt_sigaction", "rt_sigprocmask", "rt_sigreturn",
+                "rt_sigsuspend", "sigreturn", "sigaltstack",
+            ], ALLOW)
+            .with_own_priority_rules()
+            .rule_if("prlimit64", [ArgumentCondition::equal(0, 0)], ALLOW)
+            .rule_if("prlimit64", [ArgumentCondition::child_pid(0)], ALLOW)
+            .rule_if("kill", [ArgumentCondition::child_pid(0)], ALLOW)
+            .rule_if("tkill", [ArgumentCondition::child_pid(0)], ALLOW)
+            .rule_if("tgkill", [ArgumentCondition::child_pid(0)], ALLOW)
+            .rule_if("mmap", [non_executable], ALLOW)
+            .rule_if("mmap2", [non_executable], ALLOW)
+            .rule_if("mprotect", [non_executable], ALLOW)
+            .rules(["brk", "munmap", "mremap", "madvise"], ALLOW)
+            .rules([
+                "uname", "olduname", "time", "gettimeofday", "clock_gettime", "clock_gettime64",
+                "clock_getres", "clock_getres_time64", "nanosleep", "clock_nanosleep",
+                "clock_nanosleep_time64", "getuid", "getuid32", "geteuid", "geteuid32", "getgid",
+                "getgid32", "getegid", "getegid32", "getrusage", "times", "sysinfo",
+            ], ALLOW)
+            .rules([
+                "read", "readv", "pread64", "write", "writev", "lseek", "_llseek", "fstat", "fstat64",
+                "fstatat64", "statx", "close", "dup", "dup2", "dup3", "fcntl", "fcntl64",
+            ], ALLOW)
+            .rule("ioctl", ERRNO(ENOTTY))
+            .rules([
+                "open", "openat", "access", "faccessat", "faccessat2", "stat", "stat64", "lstat",
+                "lstat64", "readlink", "readlinkat", "getcwd",
+            ], ERRNO(EPERM))
+    }
+
+    /// Allows the child program to open any file for reading, and to list any directory, which
+    /// dynamically linked programs need to load their shared libraries (see [`cpp`](SyscallPolicy::cpp)).
+    ///
+    /// The filter can't check which file is being opened, so **every file the user the child program
+    /// is run as can read can then be read**, including for example the expected outputs of tests or
+    /// other solutions. Only use this if the child is run as a user without access to such files.
+    ///
+    /// The rules are added before all the rules added so far, so that they take precedence over
+    /// rules forbidding opening files, like the ones of [`cpp`](SyscallPolicy::cpp).
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```no_run
+    /// use perfjail::process::Perfjail;
+    /// use perfjail::seccomp::SyscallPolicy;
+    ///
+    /// let result = Perfjail::new("./dynamic_solution")
+    ///     .syscall_policy(SyscallPolicy::cpp().allow_reading_files())
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run the solution");
+    /// ```
+    pub fn allow_reading_files(mut self) -> SyscallPolicy {
+        let rules = mem::take(&mut self.rules);
+        self = self.with_file_reading_rules();
+        self.rules.extend(rules);
+        self
+    }
+
+    fn with_execution_control_rules(self) -> SyscallPolicy {
+        self.rules([
+            "restart_syscall", "exit", "exit_group", "arch_prctl", "set_tid_address",
+            "set_robust_list", "rseq", "futex", "getpid", "gettid", "getrandom",
+            "getrlimit", "rt_sigaction", "rt_sigprocmask", "rt_sigreturn", "rt_sigsuspend", "sigaltstack",
+        ], ALLOW)
+            .with_own_priority_rules()
+            // Resource limits and signals only concern the child itself (abort() uses tgkill)
+            .rule_if("prlimit64", [ArgumentCondition::equal(0, 0)], ALLOW)
+            .rule_if("prlimit64", [ArgumentCondition::child_pid(0)], ALLOW)
+            .rule_if("kill", [ArgumentCondition::child_pid(0)], ALLOW)
+            .rule_if("tkill", [ArgumentCondition::child_pid(0)], ALLOW)
+            .rule_if("tgkill", [ArgumentCondition::child_pid(0)], ALLOW)
+    }
+
+    fn with_own_priority_rules(self) -> SyscallPolicy {
+        // Priorities can only be read and changed for the child itself, so it can't renice other processes
+        // of the same user (like other children running at the same time)
+        let own_process = ArgumentCondition::equal(0, PRIO_PROCESS as u64);
+        self.rule_if("getpriority", [own_process, ArgumentCondition::equal(1, 0)], ALLOW)
+            .rule_if("getpriority", [own_process, ArgumentCondition::child_pid(1)], ALLOW)
+            .rule_if("setpriority", [own_process, ArgumentCondition::equal(1, 0)], ALLOW)
+            .rule_if("setpriority", [own_process, ArgumentCondition::child_pid(1)], ALLOW)
+    }
+
+    fn with_non_executable_memory_rules(self) -> SyscallPolicy {
+        self.rule_if("mmap", [ArgumentCondition::masked_equal(2, PROT_EXEC as u64, 0)], ALLOW)
+            .rule_if("mprotect", [ArgumentCondition::masked_equal(2, PROT_EXEC as u64, 0)], ALLOW)
+    }
+
+    fn with_file_mapping_rules(self) -> SyscallPolicy {
+        // Shared libraries are mapped as executable, but anonymous memory still can't be
+        self.rule_if("mmap", [ArgumentCondition::masked_equal(3, MAP_ANONYMOUS as u64, 0)], ALLOW)
+            .allow("mprotect")
+    }
+
+    fn with_memory_management_rules(self) -> SyscallPolicy {
+        self.rules(["brk", "munmap", "mremap", "madvise"], ALLOW)
+    }
+
+    fn with_system_information_rules(self) -> SyscallPolicy {
+        self.rules([
+            "uname", "time", "gettimeofday", "clock_gettime", "clock_getres", "nanosleep",
+            "clock_nanosleep", "getuid", "geteuid", "getgid", "getegid", "getrusage", "times", "sysinfo",
+        ], ALLOW)
+    }
+
+    fn with_file_reading_rules(self) -> SyscallPolicy {
+        let (mask, value) = READ_ONLY_OPEN_FLAGS;
+        self.rule_if("open", [ArgumentCondition::masked_equal(1, mask, value)], ALLOW)
+            .rule_if("openat", [ArgumentCondition::masked_equal(2, mask, value)], ALLOW)
+            // Directories opened read-only can be listed as well
+            .rules(["access", "faccessat", "faccessat2", "getdents", "getdents64"], ALLOW)
+            // Libraries probe the file systems they're loaded from while initializing (for example,
+            // libselinux looks for selinuxfs), and handle not being able to
+            .rules(["statfs", "fstatfs"], ERRNO(EPERM))
+    }
+
+    fn with_interpreter_rules(self) -> SyscallPolicy {
+        self.rules(["getdents64", "getcwd", "readlink", "readlinkat", "stat", "lstat"], ALLOW)
+    }
+
+    fn with_threading_rules(self) -> SyscallPolicy {
+        self.rules(["sched_getaffinity", "sched_yield", "membarrier"], ALLOW)
+            // Threads can be created, but processes can't. The flags of clone3 can't be checked,
+            // so the C library is made to fall back to clone
+            .rule_if("clone", [ArgumentCondition::masked_equal(0, CLONE_THREAD as u64, CLONE_THREAD as u64)], ALLOW)
+            .rule("clone3", ERRNO(ENOSYS))
+            .rule_if("prctl", [ArgumentCondition::equal(0, PR_SET_NAME as u64)], ALLOW)
+            .rule_if("prctl", [ArgumentCondition::equal(0, PR_GET_NAME as u64)], ALLOW)
+            .rule("prctl", ERRNO(EINVAL))
+            .rules(["socket", "connect", "mkdir", "unlink", "fchdir"], ERRNO(EACCES))
+    }
+
+    fn with_file_system_rules(self) -> SyscallPolicy {
+        self.rules([
+            "read", "readv", "pread64", "write", "writev", "lseek", "fstat", "newfstatat",
+            "statx", "close", "dup", "dup2", "dup3", "fcntl",
+        ], ALLOW)
+            .rule("ioctl", ERRNO(ENOTTY))
+            .rules([
+                "open", "openat", "access", "faccessat", "faccessat2", "stat", "lstat",
+                "readlink", "readlinkat", "getcwd",
+            ], ERRNO(EPERM))
+    }
+}
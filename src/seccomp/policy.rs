@@ -0,0 +1,458 @@
+use libc::{c_long, pid_t};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::process::ExecutionResult;
+use crate::seccomp::action::SeccompAction;
+use crate::seccomp::condition::ArgumentCondition;
+use crate::seccomp::syscalls::{syscall_number, SyscallAbi};
+
+/// A set of rules deciding which system calls the child program is allowed to make, used by the
+/// [`SECCOMP`](crate::process::Feature::SECCOMP) feature.
+///
+/// Rules are checked in the order they were added, and the action of the first rule matching a
+/// system call is taken. If no rule matches, the default action passed to [`SyscallPolicy::new`] is taken.
+/// Rules can also be restricted to calls whose arguments meet some conditions, see
+/// [`rule_if`](SyscallPolicy::rule_if).
+///
+/// A policy only applies to system calls made through the ABI it was created for (the native one, unless
+/// it was created with [`SyscallPolicy::new_i386`]). System calls made through other ABIs kill the child
+/// program, unless a policy for the i386 ABI is added with [`i386_policy`](SyscallPolicy::i386_policy).
+///
+/// # Examples
+///
+/// ```
+/// use perfjail::seccomp::{ArgumentCondition, SeccompAction, SyscallPolicy};
+///
+/// let policy = SyscallPolicy::new(SeccompAction::KILL)
+///     .allow("read")
+///     .rules(["write", "exit_group"], SeccompAction::ALLOW)
+///     .rule_if("kill", [ArgumentCondition::child_pid(0)], SeccompAction::ALLOW)
+///     .rule("open", SeccompAction::ERRNO(libc::EACCES));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyscallPolicy {
+    pub(crate) abi: SyscallAbi,
+    pub(crate) default_action: SeccompAction,
+    pub(crate) rules: Vec<SyscallRule>,
+    pub(crate) i386_policy: Option<Box<SyscallPolicy>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct SyscallRule {
+    pub(crate) syscall: String,
+    pub(crate) conditions: Vec<ArgumentCondition>,
+    pub(crate) action: SeccompAction,
+}
+
+impl SyscallPolicy {
+    /// Constructs a new `SyscallPolicy` with no rules, which takes `default_action` for every system call.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::seccomp::{SeccompAction, SyscallPolicy};
+    ///
+    /// // A policy which forbids every system call except for the ones allowed later
+    /// let policy = SyscallPolicy::new(SeccompAction::KILL);
+    /// ```
+    pub fn new(default_action: SeccompAction) -> SyscallPolicy {
+        SyscallPolicy {
+            abi: SyscallAbi::NATIVE,
+            default_action,
+            rules: Vec::new(),
+            i386_policy: None,
+        }
+    }
+
+    /// Constructs a new `SyscallPolicy` for system calls made through the i386 ABI, with no rules,
+    /// which takes `default_action` for every system call.
+    ///
+    /// Rules of the policy use the names of i386 system calls (like `mmap2` or `_llseek`).
+    /// To take effect, the policy has to be added to a native policy with [`i386_policy`](SyscallPolicy::i386_policy).
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::seccomp::{SeccompAction, SyscallPolicy};
+    ///
+    /// let i386_policy = SyscallPolicy::new_i386(SeccompAction::KILL)
+    ///     .rules(["read", "write", "exit_group", "mmap2"], SeccompAction::ALLOW);
+    /// ```
+    pub fn new_i386(default_action: SeccompAction) -> SyscallPolicy {
+        SyscallPolicy {
+            abi: SyscallAbi::I386,
+            ..SyscallPolicy::new(default_action)
+        }
+    }
+
+    /// Sets the policy applied to system calls made through the i386 ABI, for example by
+    /// statically linked 32-bit programs. Without it, all such system calls kill the child program.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `policy` wasn't created with [`SyscallPolicy::new_i386`] (or by a function based on it,
+    /// like [`SyscallPolicy::sio2jail_i386`]), or if this policy isn't a native policy.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::seccomp::SyscallPolicy;
+    ///
+    /// // Both 64-bit and 32-bit static programs can be run with this policy
+    /// let policy = SyscallPolicy::sio2jail_default()
+    ///     .i386_policy(SyscallPolicy::sio2jail_i386());
+    /// ```
+    pub fn i386_policy(mut self, policy: SyscallPolicy) -> SyscallPolicy {
+        assert_eq!(self.abi, SyscallAbi::NATIVE, "An i386 policy can only be added to a native policy");
+        assert_eq!(policy.abi, SyscallAbi::I386, "The policy was not created for the i386 ABI");
+
+        self.i386_policy = Some(Box::new(policy));
+        self
+    }
+
+    /// Constructs the smallest `SyscallPolicy` allowing all the system calls made during the given
+    /// executions, which have to be run with the [`SYSCALL_AUDIT`](crate::process::Feature::SYSCALL_AUDIT)
+    /// feature enabled. Every other system call kills the child program.
+    ///
+    /// This makes it possible to generate a policy for a new compiler or runtime by auditing a few
+    /// trusted programs, and then to [`save`](SyscallPolicy::save) it for later use.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of the kind [`io::ErrorKind::Unsupported`] listing the system calls unknown to perfjail,
+    /// if any were made during the executions, as rules can only be added for system calls known by name.
+    /// System calls made through the x32 ABI are ignored, since they're forbidden regardless of the policy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the executions was run without the `SYSCALL_AUDIT` feature enabled.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::process::ExitReason;
+    /// use perfjail::process::Feature::SYSCALL_AUDIT;
+    /// use perfjail::process::Perfjail;
+    /// use perfjail::seccomp::SyscallPolicy;
+    ///
+    /// let audited_result = Perfjail::new("true")
+    ///     .features(SYSCALL_AUDIT)
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run true");
+    /// let policy = SyscallPolicy::from_audited_executions([&audited_result])
+    ///     .expect("true made an unknown system call");
+    ///
+    /// let result = Perfjail::new("true")
+    ///     .syscall_policy(policy)
+    ///     .spawn()
+    ///     .expect("failed to spawn child")
+    ///     .run()
+    ///     .expect("failed to run true");
+    ///
+    /// assert_eq!(result.exit_reason, ExitReason::Exited { exit_status: 0 });
+    /// ```
+    pub fn from_audited_executions<'r, I>(results: I) -> io::Result<SyscallPolicy>
+    where
+        I: IntoIterator<Item = &'r ExecutionResult>,
+    {
+        let syscall_counts: Vec<_> = results
+            .into_iter()
+            .flat_map(|result| {
+                result.syscall_counts
+                    .as_ref()
+                    .expect("The execution was run without the SYSCALL_AUDIT feature enabled")
+            })
+            // System calls made through the x32 ABI are forbidden regardless of the policy
+            .filter(|syscall_count| syscall_count.abi != SyscallAbi::X32)
+            .collect();
+
+        let unknown_syscalls: BTreeSet<(SyscallAbi, i64)> = syscall_counts.iter()
+            .filter(|syscall_count| syscall_count.name.is_none())
+            .map(|syscall_count| (syscall_count.abi, syscall_count.number))
+            .collect();
+        if !unknown_syscalls.is_empty() {
+            let unknown_syscalls: Vec<_> = unknown_syscalls.iter()
+                .map(|(abi, number)| format!("{number} ({abi})"))
+                .collect();
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("the executions made system calls unknown to perfjail: {}", unknown_syscalls.join(", ")),
+            ));
+        }
+
+        let syscalls: BTreeSet<(SyscallAbi, &str)> = syscall_counts.iter()
+            .filter_map(|syscall_count| Some((syscall_count.abi, syscall_count.name?)))
+            .collect();
+        let syscalls_of = |abi| syscalls.iter()
+            .filter(move |(syscall_abi, _)| *syscall_abi == abi)
+            .map(|(_, name)| *name);
+
+        let policy = SyscallPolicy::new(SeccompAction::KILL)
+            .rules(syscalls_of(SyscallAbi::NATIVE), SeccompAction::ALLOW);
+        if syscalls_of(SyscallAbi::I386).next().is_some() {
+            Ok(policy.i386_policy(
+                SyscallPolicy::new_i386(SeccompAction::KILL).rules(syscalls_of(SyscallAbi::I386), SeccompAction::ALLOW)
+            ))
+        } else {
+            Ok(policy)
+        }
+    }
+
+    /// Adds a rule taking `action` whenever the child program calls the system call named `syscall`.
+    ///
+    /// To add the same rule for multiple system calls, see [`rules`](SyscallPolicy::rules).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `syscall` is not the name of a system call of the ABI of the policy.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::seccomp::{SeccompAction, SyscallPolicy};
+    ///
+    /// let policy = SyscallPolicy::new(SeccompAction::ALLOW)
+    ///     .rule("socket", SeccompAction::ERRNO(libc::EACCES))
+    ///     .rule("fork", SeccompAction::KILL);
+    /// ```
+    pub fn rule<S: AsRef<str>>(self, syscall: S, action: SeccompAction) -> SyscallPolicy {
+        self.rule_if(syscall, [], action)
+    }
+
+    /// Adds a rule taking `action` whenever the child program calls the system call named `syscall`
+    /// with arguments meeting all of the `conditions`.
+    ///
+    /// Calls which don't meet the conditions are matched against the following rules, so a rule
+    /// restricted this way is usually followed by a less permissive rule for the same system call,
+    /// or relies on the default action of the policy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `syscall` is not the name of a system call of the ABI of the policy.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::seccomp::{ArgumentCondition, SeccompAction, SyscallPolicy};
+    ///
+    /// // Anonymous memory can be mapped, but not executable memory
+    /// let policy = SyscallPolicy::new(SeccompAction::KILL)
+    ///     .rule_if("mmap", [
+    ///         ArgumentCondition::masked_equal(2, libc::PROT_EXEC as u64, 0),
+    ///         ArgumentCondition::masked_equal(3, libc::MAP_ANONYMOUS as u64, libc::MAP_ANONYMOUS as u64),
+    ///     ], SeccompAction::ALLOW)
+    ///     // Files can be opened, but only for reading
+    ///     .rule_if("openat", [
+    ///         ArgumentCondition::masked_equal(2, libc::O_ACCMODE as u64, libc::O_RDONLY as u64),
+    ///     ], SeccompAction::ALLOW)
+    ///     .rule("openat", SeccompAction::ERRNO(libc::EACCES));
+    /// ```
+    pub fn rule_if<S, I>(mut self, syscall: S, conditions: I, action: SeccompAction) -> SyscallPolicy
+    where
+        S: AsRef<str>,
+        I: IntoIterator<Item = ArgumentCondition>,
+    {
+        let syscall = syscall.as_ref();
+        assert!(
+            syscall_number(self.abi, syscall).is_some(),
+            "Unknown {} system call name: {syscall}",
+            self.abi
+        );
+
+        self.rules.push(SyscallRule {
+            syscall: syscall.to_string(),
+            conditions: conditions.into_iter().collect(),
+            action,
+        });
+        self
+    }
+
+    /// Adds a rule taking `action` whenever the child program calls any of the system calls named in `syscalls`.
+    ///
+    /// To add a rule for a single system call, see [`rule`](SyscallPolicy::rule).
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of `syscalls` is not the name of a system call of the ABI of the policy.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::seccomp::{SeccompAction, SyscallPolicy};
+    ///
+    /// let policy = SyscallPolicy::new(SeccompAction::KILL)
+    ///     .rules(["read", "write", "exit_group"], SeccompAction::ALLOW);
+    /// ```
+    pub fn rules<I, S>(mut self, syscalls: I, action: SeccompAction) -> SyscallPolicy
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for syscall in syscalls {
+            self = self.rule(syscall, action);
+        }
+        self
+    }
+
+    /// Adds a rule allowing the child program to call the system call named `syscall`.
+    ///
+    /// This is equivalent to calling [`rule`](SyscallPolicy::rule) with [`SeccompAction::ALLOW`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `syscall` is not the name of a system call of the ABI of the policy.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use perfjail::seccomp::{SeccompAction, SyscallPolicy};
+    ///
+    /// let policy = SyscallPolicy::new(SeccompAction::KILL)
+    ///     .allow("read")
+    ///     .allow("write");
+    /// ```
+    pub fn allow<S: AsRef<str>>(self, syscall: S) -> SyscallPolicy {
+        self.rule(syscall, SeccompAction::ALLOW)
+    }
+
+    /// Loads a policy from the file at `path`, written in a text format which is also produced by
+    /// [`save`](SyscallPolicy::save). Policies can also be parsed from strings with [`str::parse`].
+    ///
+    /// Every line of the file contains a single statement, and `#` starts a comment:
+    ///
+    /// ```text
+    /// # The action taken for system calls not matching any rule
+    /// default kill
+    /// # Rules, checked in order: <action> <syscall> [if <condition> [and <condition>]...]
+    /// allow read
+    /// allow mmap if arg2 & 0x4 == 0x0
+    /// allow kill if arg0 == child_pid
+    /// errno(EACCES) socket
+    /// ```
+    ///
+    /// Actions are written as `allow`, `trace`, `kill` or `errno(<errno>)`, where `<errno>` is either the
+    /// name of an error (like `EPERM`) or its number, which can be zero to make the system call return 0 without
+    /// being executed. Conditions compare an argument (from `arg0` to `arg5`)
+    /// with a number using `==`, `!=`, `>`, `>=`, `<` or `<=`, or with the pid of the child using `== child_pid`.
+    /// Arguments can also be masked with `& <mask>` before being compared with `==`. Numbers are written
+    /// in decimal or in hexadecimal with a `0x` prefix, and the parts of conditions are separated with spaces.
+    ///
+    /// Rules used in multiple places can be defined once in a named group, and then added with `use`:
+    ///
+    /// ```text
+    /// group output
+    ///     allow write
+    ///     allow writev
+    /// end
+    ///
+    /// default kill
+    /// use output
+    /// allow exit_group
+    /// ```
+    ///
+    /// An [i386 policy](SyscallPolicy::i386_policy) follows an `abi i386` line after the rules of the
+    /// native policy, starting with its own `default` line.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read. If it isn't a valid policy, the error has the kind
+    /// [`InvalidData`](io::ErrorKind::InvalidData) and wraps a [`PolicyParseError`](crate::seccomp::PolicyParseError)
+    /// describing the problem and the line it occurred on.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```no_run
+    /// use perfjail::process::Perfjail;
+    /// use perfjail::seccomp::SyscallPolicy;
+    ///
+    /// let policy = SyscallPolicy::load("cpp.policy").expect("failed to load the policy");
+    /// let child = Perfjail::new("./solution")
+    ///     .syscall_policy(policy)
+    ///     .spawn();
+    /// ```
+    ///
+    /// Parsing a policy from a string:
+    ///
+    /// ```
+    /// use perfjail::seccomp::SyscallPolicy;
+    ///
+    /// let error = "default kill\nallow read\nallow foo".parse::<SyscallPolicy>().unwrap_err();
+    /// assert_eq!(error.line(), 3);
+    /// assert_eq!(error.to_string(), "line 3: unknown native system call `foo`");
+    /// ```
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<SyscallPolicy> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Saves the policy to the file at `path` in the text format described in the documentation of
+    /// [`load`](SyscallPolicy::load), which is also produced by the [`Display`](std::fmt::Display)
+    /// implementation of `SyscallPolicy`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```no_run
+    /// use perfjail::seccomp::SyscallPolicy;
+    ///
+    /// SyscallPolicy::cpp()
+    ///     .save("cpp.policy")
+    ///     .expect("failed to save the policy");
+    /// ```
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    /// Returns the action this policy takes for the system call with the number `syscall` made
+    /// through `abi` with `args` by the child with the pid `child_pid`, along with the matching
+    /// rule (or [`None`] if the default action is taken).
+    ///
+    /// System calls made through ABIs without a policy (or an unknown ABI) are always killed.
+    pub(crate) fn evaluate(
+        &self,
+        abi: Option<SyscallAbi>,
+        syscall: c_long,
+        args: &[u64; 6],
+        child_pid: pid_t
+    ) -> (SeccompAction, Option<&SyscallRule>) {
+        if abi != Some(self.abi) {
+            return match (abi, self.i386_policy.as_ref()) {
+                (Some(SyscallAbi::I386), Some(i386_policy)) => i386_policy.evaluate(abi, syscall, args, child_pid),
+                _ => (SeccompAction::KILL, None),
+            };
+        }
+
+        self.rules
+            .iter()
+            .find(|rule| {
+                syscall_number(self.abi, &rule.syscall) == Some(syscall) &&
+                    rule.conditions.iter().all(|condition| condition.evaluate(args, child_pid))
+            })
+            .map_or((self.default_action, None), |rule| (rule.action, Some(rule)))
+    }
+}
@@ -1,7 +1,17 @@
 use std::io;
-use libc::{c_int, pid_t, size_t, SIGKILL};
-use std::io::Error;
+use libc::{
+    c_int, fcntl, memfd_create, pid_t, size_t, F_ADD_SEALS, F_SEAL_GROW, F_SEAL_SEAL, F_SEAL_SHRINK, F_SEAL_WRITE,
+    MFD_ALLOW_SEALING, MFD_CLOEXEC, SIGKILL,
+};
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{Error, Read, Seek};
+use std::os::fd::{AsRawFd, FromRawFd};
 use cvt::cvt;
+use nix::sched::{sched_getaffinity, sched_setaffinity, CpuSet};
+use nix::unistd::{mkdtemp, Pid};
+use std::path::{Path, PathBuf};
+use std::{env, fs};
 
 /// The stack size (in bytes) for creating the child process with [`clone`].
 ///
@@ -11,10 +21,6 @@ pub(crate) const CHILD_STACK_SIZE: size_t = 65536;
 /// The number of CPU cycles perfjail considers equal to 1 second in measured time.
 pub(crate) const CYCLES_PER_SECOND: i64 = 2_000_000_000;
 
-pub(crate) fn errno() -> i32 {
-    Error::last_os_error().raw_os_error().unwrap()
-}
-
 pub(crate) fn cvt_no_errno(argument: c_int) -> io::Result<()> {
     if argument == 0 {
         Ok(())
@@ -28,3 +34,72 @@ pub(crate) fn kill_pid(pid: pid_t) -> io::Result<()> {
         cvt(libc::kill(pid, SIGKILL)).map(|_| ())
     }
 }
+
+/// Creates a set of the CPUs with the given indices.
+///
+/// # Panics
+///
+/// Panics if any of the indices is too large to be stored in a [`CpuSet`].
+pub(crate) fn cpu_set<I: IntoIterator<Item = usize>>(cpus: I) -> CpuSet {
+    let mut cpu_set = CpuSet::new();
+    for cpu in cpus {
+        cpu_set.set(cpu).unwrap_or_else(|_| panic!("CPU {cpu} is out of range"));
+    }
+
+    cpu_set
+}
+
+/// Pins the calling thread to a set of CPUs, restoring its previous affinity when dropped.
+pub(crate) struct PinnedThread {
+    previous_cpu_set: CpuSet,
+}
+
+impl PinnedThread {
+    pub(crate) fn pin(cpu_set: &CpuSet) -> io::Result<PinnedThread> {
+        let previous_cpu_set = sched_getaffinity(Pid::from_raw(0))?;
+        sched_setaffinity(Pid::from_raw(0), cpu_set)?;
+
+        Ok(PinnedThread { previous_cpu_set })
+    }
+}
+
+impl Drop for PinnedThread {
+    fn drop(&mut self) {
+        sched_setaffinity(Pid::from_raw(0), &self.previous_cpu_set).expect("Failed to restore the CPU affinity");
+    }
+}
+
+/// A new empty directory in the temporary directory, which is removed along with its contents when dropped.
+#[derive(Debug)]
+pub(crate) struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub(crate) fn new() -> io::Result<TempDir> {
+        let path = mkdtemp(&env::temp_dir().join("perfjail-XXXXXX"))?;
+        Ok(TempDir { path })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // The directory can't be removed if the child made it read-only, in which case it's left behind
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Creates a file in memory containing everything read from `data`, which is sealed so its contents can't be changed
+/// anymore. The offset of the returned file is at its start.
+pub(crate) fn sealed_memfd<R: Read>(name: &CStr, mut data: R) -> io::Result<File> {
+    let mut file = unsafe { File::from_raw_fd(cvt(memfd_create(name.as_ptr(), MFD_CLOEXEC | MFD_ALLOW_SEALING))?) };
+    io::copy(&mut data, &mut file)?;
+    cvt(unsafe { fcntl(file.as_raw_fd(), F_ADD_SEALS, F_SEAL_SEAL | F_SEAL_SHRINK | F_SEAL_GROW | F_SEAL_WRITE) })?;
+    file.rewind()?;
+
+    Ok(file)
+}